@@ -0,0 +1,42 @@
+use std::{env, path::Path, process::ExitCode};
+
+mod maps;
+mod verify;
+
+const USAGE: &str = "Usage:
+  soundaim-cli verify <map file> <replay file>
+      Judges the replay on the map and checks it against the result saved in the replay.
+      Exits with 1 if they are not the same.
+  soundaim-cli info <map files or directories...>
+      Prints the notes, length, density and bounds of every map.
+  soundaim-cli convert <input map> <output map>
+      Rewrites the map in the format of the output extension (.txt or .sspm).
+  soundaim-cli validate <map files or directories...>
+      Checks every map for notes the game can't play properly.
+      Exits with 1 if any map has problems.";
+
+// Exit code for a bad command line or a file that could not be read or written
+const ERROR_EXIT_CODE: u8 = 2;
+
+// Tools working on the game files, without a window or audio
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    let result = match args.as_slice() {
+        ["verify", map, replay] => verify::run(Path::new(map), Path::new(replay)),
+        ["info", paths @ ..] if !paths.is_empty() => maps::info(paths),
+        ["convert", input, output] => maps::convert(Path::new(input), Path::new(output)),
+        ["validate", paths @ ..] if !paths.is_empty() => maps::validate(paths),
+        _ => {
+            eprintln!("{0}", USAGE);
+            return ExitCode::from(ERROR_EXIT_CODE);
+        }
+    };
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Error: {0}", err);
+            ExitCode::from(ERROR_EXIT_CODE)
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use std::{fs, path::{Path, PathBuf}, process::ExitCode};
+
+use soundaim::{engine::{autoplay::{run_autoplay, AUTOPLAY_CURVES}, get_play_notes, judgement::{HitResult, JudgementWindows}}, map::{format_length, read_map_file, sspm::{write_sspm, SspmMap}, v1::write_v1_notes, MapFile, Note, NoteData, GRID_HALF_SIZE, METADATA_FILE}, modifiers::Modifiers, scores::map_id};
+
+// Map files the game loads, everything else in a directory is ignored
+const MAP_EXTENSIONS: [&str; 2] = ["txt", "sspm"];
+const AUDIO_EXTENSIONS: [&str; 3] = ["mp3", "ogg", "wav"];
+// The peak density is the most notes in a window this long
+const DENSITY_WINDOW_MS: i128 = 1000;
+// Autoplay is checked at 60 FPS, starting to move when the notes show up with the default approach rate
+const AUTOPLAY_FRAME_MS: i128 = 16;
+const AUTOPLAY_APPROACH_MS: i128 = 500;
+
+// Prints what the game would load from every map
+pub fn info(paths: &[&str]) -> Result<ExitCode, String> {
+    let map_paths = find_maps(paths)?;
+    let mut unreadable = 0;
+    for (i, path) in map_paths.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{0}", path.display());
+        let map = match read_map_file(path) {
+            Ok(map) => map,
+            Err(err) => {
+                println!("  Error: {0}", err);
+                unreadable += 1;
+                continue;
+            }
+        };
+        let metadata = &map.metadata;
+        if !metadata.title.is_empty() {
+            println!("  Song: {0} - {1}", metadata.artist, metadata.title);
+        }
+        if !metadata.mapper.is_empty() {
+            println!("  Mapper: {0}", metadata.mapper);
+        }
+        if !metadata.difficulty.is_empty() {
+            println!("  Difficulty: {0}", metadata.difficulty);
+        }
+        let notes = &map.notes.0;
+        println!("  Notes: {0}", notes.len());
+        println!("  Length: {0}", format_length(map.notes.length_ms()));
+        if metadata.offset != 0 {
+            println!("  Offset: {0}ms", metadata.offset);
+        }
+        let (average, peak) = density(&map.notes);
+        println!("  Density: {0:.2} notes/s average, {1} notes/s peak", average, peak);
+        let off_grid = notes.iter().filter(|note| !is_on_grid(note.x) || !is_on_grid(note.y)).count();
+        let outside = notes.iter().filter(|note| !is_inside_grid(note.x) || !is_inside_grid(note.y)).count();
+        println!("  Bounds: {0} on the grid, {1} between cells (quantum), {2} outside of the grid", notes.len() - off_grid, off_grid - outside, outside);
+        let embedded: Vec<&str> = [(map.audio.is_some(), "audio"), (map.cover.is_some(), "cover")].into_iter()
+            .filter_map(|(is_embedded, name)| is_embedded.then_some(name))
+            .collect();
+        if !embedded.is_empty() {
+            println!("  Embedded: {0}", embedded.join(", "));
+        }
+        println!("  Map id: {0}", map_id(&map.notes));
+    }
+    if unreadable > 0 {
+        return Err(format!("{0} of {1} maps could not be read", unreadable, map_paths.len()));
+    }
+    return Ok(ExitCode::SUCCESS);
+}
+
+// Checks every map for notes the game can't play properly, exits with 1 if any map has problems
+pub fn validate(paths: &[&str]) -> Result<ExitCode, String> {
+    let map_paths = find_maps(paths)?;
+    let mut invalid = 0;
+    for path in &map_paths {
+        let problems = match read_map_file(path) {
+            Ok(map) => find_problems(&map),
+            Err(err) => vec![err.to_string()],
+        };
+        if problems.is_empty() {
+            continue;
+        }
+        invalid += 1;
+        println!("{0}", path.display());
+        for problem in problems {
+            println!("  {0}", problem);
+        }
+    }
+    if invalid > 0 {
+        println!("{0} of {1} maps have problems", invalid, map_paths.len());
+        return Ok(ExitCode::FAILURE);
+    }
+    println!("{0} maps checked, no problems", map_paths.len());
+    return Ok(ExitCode::SUCCESS);
+}
+
+// Rewrites the map in the format of the output extension.
+// A .txt map gets its song and cover next to it and its metadata in a map.json, like a map folder.
+// A .sspm map embeds them, with the offset added to the note times since SSPM has no offset.
+pub fn convert(input: &Path, output: &Path) -> Result<ExitCode, String> {
+    let map = read_map_file(input).map_err(|err| format!("{0}: {1}", input.display(), err))?;
+    let id = output.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    match extension(output).as_str() {
+        "txt" => convert_to_v1(input, output, &id, map)?,
+        "sspm" => convert_to_sspm(input, output, &id, map)?,
+        extension => return Err(format!("Can't convert to {0:?}, the output has to be a .txt or .sspm file", extension)),
+    }
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn convert_to_v1(input: &Path, output: &Path, id: &str, map: MapFile) -> Result<(), String> {
+    let mut metadata = map.metadata;
+    let metadata_path = output.with_file_name(METADATA_FILE);
+    let source_metadata_path = input.with_file_name(METADATA_FILE);
+    let has_metadata = extension(input) == "sspm" || source_metadata_path.is_file();
+    // Checked before writing anything so a failed conversion doesn't leave half a map
+    if has_metadata && metadata_path.exists() && !is_same_file(&metadata_path, &source_metadata_path) {
+        return Err(format!("{0} already exists, convert the map into its own folder", metadata_path.display()));
+    }
+
+    write_file(output, write_v1_notes(id, &map.notes).as_bytes())?;
+    if let Some(audio) = &map.audio {
+        match audio_extension(audio) {
+            Some(audio_extension) => {
+                let audio_path = output.with_extension(audio_extension);
+                write_file(&audio_path, audio)?;
+                metadata.audio = Some(file_name(&audio_path));
+            }
+            None => eprintln!("Warning: the embedded audio is not mp3, ogg or wav, it was not extracted"),
+        }
+    }
+    // SSPM covers are always PNG
+    if let Some(cover) = &map.cover {
+        let cover_path = output.with_extension("png");
+        write_file(&cover_path, cover)?;
+        metadata.background = Some(file_name(&cover_path));
+    }
+    if has_metadata && !is_same_file(&metadata_path, &source_metadata_path) {
+        if extension(input) == "txt" && (metadata.audio.is_some() || metadata.background.is_some()) {
+            eprintln!("Warning: the audio and background of {0} are not copied, their paths are relative to its folder", METADATA_FILE);
+        }
+        let json = serde_json::to_string_pretty(&metadata).map_err(|err| err.to_string())?;
+        write_file(&metadata_path, json.as_bytes())?;
+    }
+    return Ok(());
+}
+
+fn convert_to_sspm(input: &Path, output: &Path, id: &str, map: MapFile) -> Result<(), String> {
+    let metadata = &map.metadata;
+    let notes = NoteData(map.notes.0.iter().map(|note| Note {
+        hit_ms: note.hit_ms + metadata.offset,
+        ..*note
+    }).collect());
+    let name = match (metadata.artist.is_empty(), metadata.title.is_empty()) {
+        (false, false) => format!("{0} - {1}", metadata.artist, metadata.title),
+        (true, false) => metadata.title.clone(),
+        _ => id.to_owned(),
+    };
+
+    // Files of a .txt map are next to it, or named in its map.json
+    let source_file = |path: &Option<String>, extensions: &[&str]| -> Option<PathBuf> {
+        if let Some(path) = path {
+            return Some(input.with_file_name(path)).filter(|path| path.is_file());
+        }
+        return extensions.iter().map(|extension| input.with_extension(extension)).find(|path| path.is_file());
+    };
+    let read = |path: PathBuf| fs::read(&path).map_err(|err| format!("{0}: {1}", path.display(), err));
+    let audio = match map.audio {
+        Some(audio) => Some(audio),
+        None => source_file(&metadata.audio, &AUDIO_EXTENSIONS).map(read).transpose()?,
+    };
+    let cover = match map.cover {
+        Some(cover) => Some(cover),
+        None => source_file(&metadata.background, &[]).filter(|path| extension(path) == "png").map(read).transpose()?,
+    };
+    if audio.is_none() {
+        eprintln!("Warning: no audio found for {0}, the map has no song", input.display());
+    }
+
+    let sspm = SspmMap {
+        id: id.to_owned(),
+        song_name: name.clone(),
+        name,
+        mappers: metadata.mapper.split(", ").filter(|mapper| !mapper.is_empty()).map(str::to_owned).collect(),
+        difficulty: 0,
+        difficulty_name: Some(metadata.difficulty.clone()).filter(|difficulty| !difficulty.is_empty()),
+        notes,
+        audio,
+        cover,
+    };
+    let bytes = write_sspm(&sspm).map_err(|err| format!("{0}: {1}", input.display(), err))?;
+    return write_file(output, &bytes);
+}
+
+// Everything that would make the game play the map wrong
+fn find_problems(map: &MapFile) -> Vec<String> {
+    let mut problems = vec![];
+    let notes = &map.notes.0;
+    for (i, note) in notes.iter().enumerate() {
+        if !note.x.is_finite() || !note.y.is_finite() {
+            problems.push(format!("Note {0} has an invalid position", i));
+        } else if !is_inside_grid(note.x) || !is_inside_grid(note.y) {
+            problems.push(format!("Note {0} is outside of the grid ({1}, {2})", i, note.x, note.y));
+        }
+        let hit_ms = note.hit_ms + map.metadata.offset;
+        if hit_ms < 0 {
+            problems.push(format!("Note {0} is hit before the song starts ({1}ms)", i, hit_ms));
+        }
+        // Notes are sorted, a duplicate is right before it
+        let duplicate = notes[..i].iter().rev()
+            .take_while(|other| other.hit_ms == note.hit_ms)
+            .position(|other| other.x == note.x && other.y == note.y);
+        if let Some(offset) = duplicate {
+            problems.push(format!("Note {0} is a duplicate of note {1}", i, i - 1 - offset));
+        }
+    }
+    // Autoplay hits every note a player can, a note it misses can't be played.
+    // The cursor can't follow notes without a position, they are already reported.
+    if notes.iter().any(|note| !note.x.is_finite() || !note.y.is_finite()) {
+        return problems;
+    }
+    let modifiers = Modifiers::default();
+    let play_notes = get_play_notes(notes, map.metadata.offset, &modifiers);
+    for curve in AUTOPLAY_CURVES {
+        let play = run_autoplay(&play_notes, &modifiers, JudgementWindows::default(), curve, AUTOPLAY_APPROACH_MS, AUTOPLAY_FRAME_MS);
+        let mut misses = play.judgements.iter().filter(|judgement| judgement.result == HitResult::Miss);
+        if let Some(first) = misses.next() {
+            problems.push(format!("Autoplay ({0:?}) misses {1} notes, the first one at {2}ms", curve, misses.count() + 1, first.hit_ms));
+        }
+    }
+    return problems;
+}
+
+// Average notes per second over the map, and the most notes in a second
+fn density(notes: &NoteData) -> (f32, usize) {
+    let notes = &notes.0;
+    let duration_ms = notes.last().map_or(0, |note| note.hit_ms) - notes.first().map_or(0, |note| note.hit_ms);
+    // Maps shorter than the window would be denser on average than at their peak
+    let average = notes.len() as f32 / (duration_ms.max(DENSITY_WINDOW_MS) as f32 / 1000.);
+    let mut peak = 0;
+    let mut start = 0;
+    for (end, note) in notes.iter().enumerate() {
+        while note.hit_ms - notes[start].hit_ms >= DENSITY_WINDOW_MS {
+            start += 1;
+        }
+        peak = peak.max(end - start + 1);
+    }
+    return (average, peak);
+}
+
+// On the center of a grid cell
+fn is_on_grid(value: f32) -> bool {
+    return value.fract() == 0. && (-1. ..=1.).contains(&value);
+}
+
+fn is_inside_grid(value: f32) -> bool {
+    return value.abs() <= GRID_HALF_SIZE;
+}
+
+// The map files in the given paths, directories are searched recursively
+fn find_maps(paths: &[&str]) -> Result<Vec<PathBuf>, String> {
+    let mut maps = vec![];
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            find_maps_in(path, &mut maps).map_err(|err| format!("{0}: {1}", path.display(), err))?;
+        } else if path.is_file() {
+            maps.push(path.to_path_buf());
+        } else {
+            return Err(format!("{0} does not exist", path.display()));
+        }
+    }
+    if maps.is_empty() {
+        return Err("No maps found".to_owned());
+    }
+    return Ok(maps);
+}
+
+fn find_maps_in(dir: &Path, maps: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            find_maps_in(&entry, maps)?;
+        } else if MAP_EXTENSIONS.contains(&extension(&entry).as_str()) {
+            maps.push(entry);
+        }
+    }
+    return Ok(());
+}
+
+// Detected from the first bytes since SSPM doesn't store the format
+fn audio_extension(audio: &[u8]) -> Option<&'static str> {
+    if audio.starts_with(b"OggS") {
+        return Some("ogg");
+    } else if audio.starts_with(b"RIFF") {
+        return Some("wav");
+    } else if audio.starts_with(b"ID3") || (audio.len() > 1 && audio[0] == 0xff && audio[1] & 0xe0 == 0xe0) {
+        return Some("mp3");
+    }
+    return None;
+}
+
+fn extension(path: &Path) -> String {
+    return path.extension().map_or(String::new(), |extension| extension.to_string_lossy().to_lowercase());
+}
+
+fn file_name(path: &Path) -> String {
+    return path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    return a.canonicalize().ok().is_some_and(|a| b.canonicalize().ok().is_some_and(|b| a == b));
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    fs::write(path, bytes).map_err(|err| format!("{0}: {1}", path.display(), err))?;
+    println!("Wrote {0}", path.display());
+    return Ok(());
+}
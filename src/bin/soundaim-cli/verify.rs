@@ -0,0 +1,79 @@
+use std::{path::Path, process::ExitCode};
+
+use soundaim::{engine::{get_play_notes, scoring::get_play_grade_name, simulate}, map::read_map_file, replay::Replay, scores::{format_date, map_id}};
+
+// The accuracy is saved as a f32, closer than this is the same
+const ACCURACY_TOLERANCE: f32 = 0.01;
+
+// Judges the replay on the map like the game did, prints every judgement and the result.
+// The result has to be the one saved in the replay, otherwise the replay was not made by this version of the game (or was edited).
+// Replays with modifiers or windows the game doesn't allow are rejected before being judged.
+pub fn run(map_path: &Path, replay_path: &Path) -> Result<ExitCode, String> {
+    let map = read_map_file(map_path).map_err(|err| format!("{0}: {1}", map_path.display(), err))?;
+    let replay = Replay::load(replay_path).map_err(|err| format!("{0}: {1}", replay_path.display(), err))?;
+    // Replays are matched to the notes as they are in the map file
+    let id = map_id(&map.notes);
+    if id != replay.map_id {
+        return Err(format!("the replay is of another map (map id {0}, this map is {1})", replay.map_id, id));
+    }
+
+    if let Err(reason) = replay.validate() {
+        println!("Not verified, {0}", reason);
+        return Ok(ExitCode::FAILURE);
+    }
+
+    let notes = get_play_notes(&map.notes.0, map.metadata.offset, &replay.modifiers);
+    let play = simulate(&notes, &replay.modifiers, replay.windows, &replay.frames);
+
+    println!("Replay of {0} from {1}", replay.map_title, format_date(replay.date));
+    let mods = replay.modifiers.acronyms();
+    println!("Modifiers: {0}, speed {1:.1}x", if mods.is_empty() { "none".to_owned() } else { mods.join(" ") }, replay.modifiers.speed);
+    println!("Windows: Perfect {0}ms, Great {1}ms", replay.windows.perfect_ms, replay.windows.great_ms);
+    println!();
+    for (i, judgement) in play.judgements.iter().enumerate() {
+        match judgement.offset_ms {
+            Some(offset_ms) => println!("{0:>5} {1:>9}ms {2:<8}{3:+}ms", i + 1, judgement.hit_ms, judgement.result.name(), offset_ms),
+            None => println!("{0:>5} {1:>9}ms {2}", i + 1, judgement.hit_ms, judgement.result.name()),
+        }
+    }
+    println!();
+
+    let accuracy = play.accuracy();
+    let score = play.score();
+    let failed = play.failed_at_ms.is_some();
+    println!("Accuracy: {0:.2}%", accuracy);
+    println!("Grade: {0}", get_play_grade_name(play.grade()));
+    println!("Score: {0}", score);
+    println!("Hits: {0}/{1}, misses: {2}, max combo: {3}", play.hits, notes.len(), play.misses, play.max_combo);
+    if let Some(failed_at_ms) = play.failed_at_ms {
+        println!("Failed at {0}ms", failed_at_ms);
+    }
+    let unjudged = notes.len() - play.judgements.len();
+    if !failed && unjudged > 0 {
+        println!("The replay ends before {0} notes", unjudged);
+    }
+
+    let mut mismatches: Vec<String> = vec![];
+    if score != replay.score {
+        mismatches.push(format!("score {0}, saved {1}", score, replay.score));
+    }
+    if (accuracy - replay.accuracy).abs() > ACCURACY_TOLERANCE {
+        mismatches.push(format!("accuracy {0:.2}%, saved {1:.2}%", accuracy, replay.accuracy));
+    }
+    if play.hits != replay.hits || play.misses != replay.misses || play.max_combo != replay.max_combo {
+        mismatches.push(format!("{0} hits, {1} misses, {2} max combo, saved {3}, {4}, {5}", play.hits, play.misses, play.max_combo, replay.hits, replay.misses, replay.max_combo));
+    }
+    if failed != replay.failed {
+        mismatches.push(format!("failed {0}, saved {1}", failed, replay.failed));
+    }
+    println!();
+    if mismatches.is_empty() {
+        println!("Verified, the result is the one saved in the replay");
+        return Ok(ExitCode::SUCCESS);
+    }
+    println!("Not verified, the result is not the one saved in the replay:");
+    for mismatch in mismatches {
+        println!("  {0}", mismatch);
+    }
+    return Ok(ExitCode::FAILURE);
+}
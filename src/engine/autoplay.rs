@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+use crate::modifiers::Modifiers;
+
+use super::{judgement::{JudgementWindows, NOTE_LATE_HIT_WINDOW}, simulation::PlaySimulation, Note, Vec2};
+
+// How long before the hit time a flick starts moving (ms)
+const FLICK_MS: i128 = 80;
+
+// How the autoplay cursor moves from one note to the next
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum AutoplayCurve {
+    // Constant speed
+    Linear,
+    // Speeds up then slows down into the note
+    Eased,
+    // Waits on the last note, then snaps to the next one right before it has to be hit
+    Flick
+}
+
+pub const AUTOPLAY_CURVES: [AutoplayCurve; 3] = [AutoplayCurve::Linear, AutoplayCurve::Eased, AutoplayCurve::Flick];
+
+// Moves the autoplay cursor to the next note that has to be hit.
+// The cursor is right on the note from its hit time until it is hit, so autoplay never misses.
+#[derive(Default)]
+pub struct AutoplayPath {
+    from: Vec2,
+    from_ms: i128,
+    // Position and hit time of the note the cursor is moving to
+    target: Option<(Vec2, i128)>,
+}
+
+impl AutoplayPath {
+    // Where the cursor goes at `time_ms`, `target` is the next note that is not hit yet.
+    // A new target is moved to from wherever the cursor is when it shows up.
+    pub fn update(&mut self, cursor: Vec2, target: Option<(Vec2, i128)>, time_ms: i128, curve: AutoplayCurve) -> Vec2 {
+        let Some((target_pos, hit_ms)) = target else {
+            self.target = None;
+            return cursor;
+        };
+        if self.target != target {
+            self.from = cursor;
+            self.from_ms = time_ms;
+            self.target = target;
+        }
+        let start_ms = match curve {
+            AutoplayCurve::Flick => self.from_ms.max(hit_ms - FLICK_MS),
+            AutoplayCurve::Linear | AutoplayCurve::Eased => self.from_ms,
+        };
+        let progress = if hit_ms <= start_ms {
+            1.
+        } else {
+            ((time_ms - start_ms) as f32 / (hit_ms - start_ms) as f32).clamp(0., 1.)
+        };
+        return self.from.lerp(target_pos, apply_curve(curve, progress));
+    }
+}
+
+// Eases a 0 - 1 progress, 0 and 1 stay the same
+fn apply_curve(curve: AutoplayCurve, progress: f32) -> f32 {
+    match curve {
+        AutoplayCurve::Linear => progress,
+        AutoplayCurve::Eased => progress * progress * (3. - 2. * progress),
+        AutoplayCurve::Flick => 1. - (1. - progress).powi(3),
+    }
+}
+
+// Plays the notes with the autoplay cursor, one frame every `frame_ms`, like the game does at that frame rate.
+// The cursor starts moving to a note when it shows up, `approach_rate` ms before its hit time.
+pub fn run_autoplay(notes: &[Note], modifiers: &Modifiers, windows: JudgementWindows, curve: AutoplayCurve, approach_rate: i128, frame_ms: i128) -> PlaySimulation {
+    let mut play = PlaySimulation::new(notes, modifiers, windows);
+    let mut path = AutoplayPath::default();
+    let mut cursor = Vec2::ZERO;
+    let end_ms = play.last_note_ms() + NOTE_LATE_HIT_WINDOW;
+    let mut time_ms = 0;
+    while time_ms <= end_ms + frame_ms {
+        let target = play.next_note()
+            .filter(|note| note.hit_ms - approach_rate < time_ms)
+            .map(|note| (Vec2::new(note.x, note.y), note.hit_ms));
+        cursor = path.update(cursor, target, time_ms, curve);
+        play.update(time_ms, cursor.x, cursor.y);
+        time_ms += frame_ms.max(1);
+    }
+    return play;
+}
@@ -0,0 +1,102 @@
+use super::Note;
+
+// Health goes from 0 to 1, a miss drains MISS_DRAIN on a map with REFERENCE_DENSITY notes per second.
+// Denser maps drain less per miss (and sparser ones more) so that failing takes about as long on any map.
+const MISS_DRAIN: f32 = 0.15;
+const REFERENCE_DENSITY: f32 = 3.;
+const MIN_MISS_DRAIN: f32 = 0.04;
+const MAX_MISS_DRAIN: f32 = 0.3;
+// A hit gives back this part of what a miss drains
+const HIT_REFILL_RATIO: f32 = 0.25;
+
+#[derive(Clone)]
+pub struct PlayHealth {
+    pub health: f32,
+    miss_drain: f32,
+    hit_refill: f32,
+    // Off with no fail
+    pub enabled: bool,
+}
+
+impl Default for PlayHealth {
+    fn default() -> Self {
+        PlayHealth {
+            health: 1.,
+            miss_drain: MISS_DRAIN,
+            hit_refill: MISS_DRAIN * HIT_REFILL_RATIO,
+            enabled: true,
+        }
+    }
+}
+
+impl PlayHealth {
+    // The notes are in hit order, the density is only measured between the first and the last one
+    // so that an intro or the map offset doesn't make a map look sparse
+    pub fn new(notes: &[Note], play_speed: f32, enabled: bool) -> PlayHealth {
+        let length_ms = match (notes.first(), notes.last()) {
+            (Some(first), Some(last)) => last.hit_ms - first.hit_ms,
+            _ => 0,
+        };
+        let length_secs = length_ms as f32 / 1000. / play_speed;
+        let density = if length_secs > 0. { notes.len() as f32 / length_secs } else { REFERENCE_DENSITY };
+        let miss_drain = (MISS_DRAIN * REFERENCE_DENSITY / density.max(0.1)).clamp(MIN_MISS_DRAIN, MAX_MISS_DRAIN);
+        PlayHealth {
+            health: 1.,
+            miss_drain,
+            hit_refill: miss_drain * HIT_REFILL_RATIO,
+            enabled,
+        }
+    }
+
+    pub fn on_hit(&mut self) {
+        if self.enabled {
+            self.health = (self.health + self.hit_refill).min(1.);
+        }
+    }
+
+    pub fn on_miss(&mut self) {
+        if self.enabled {
+            self.health = (self.health - self.miss_drain).max(0.);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.enabled && self.health <= 0.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Note;
+
+    use super::{PlayHealth, MAX_MISS_DRAIN, MISS_DRAIN};
+
+    // `count` notes every `gap_ms`, the first one at `start_ms`
+    fn notes(start_ms: i128, count: usize, gap_ms: i128) -> Vec<Note> {
+        return (0..count).map(|i| Note { hit_ms: start_ms + i as i128 * gap_ms, x: 0., y: 0., size: 1. }).collect();
+    }
+
+    #[test]
+    fn intro_does_not_change_the_drain() {
+        // 10 notes per second
+        let dense = PlayHealth::new(&notes(0, 100, 100), 1., true);
+        let with_intro = PlayHealth::new(&notes(60000, 100, 100), 1., true);
+        assert!(dense.miss_drain < MISS_DRAIN);
+        assert_eq!(with_intro.miss_drain, dense.miss_drain);
+        assert!(with_intro.miss_drain < MAX_MISS_DRAIN);
+    }
+
+    #[test]
+    fn sparse_notes_drain_more() {
+        let dense = PlayHealth::new(&notes(1000, 100, 100), 1., true);
+        let sparse = PlayHealth::new(&notes(1000, 100, 1000), 1., true);
+        assert!(sparse.miss_drain > dense.miss_drain);
+    }
+
+    #[test]
+    fn notes_at_the_same_time_use_the_reference_drain() {
+        for notes in [vec![], notes(5000, 1, 0), notes(5000, 3, 0)] {
+            assert_eq!(PlayHealth::new(&notes, 1., true).miss_drain, MISS_DRAIN);
+        }
+    }
+}
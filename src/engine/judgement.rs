@@ -0,0 +1,151 @@
+
+use super::{Note, Vec2};
+
+pub const NOTE_EARLY_HIT_WINDOW: i128 = 0;
+pub const NOTE_LATE_HIT_WINDOW: i128 = 200;// How long after its hit time a note can still be hit before it is missed
+const CURSOR_HITBOX: f32 = 0.2625/2.;
+// Smallest Perfect and Great windows (ms)
+pub const MIN_JUDGEMENT_WINDOW: i128 = 5;
+
+// Judgement of a note, hits are split in tiers by how far from the hit time they were
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum HitResult {
+    Perfect, Great, Good, Miss
+}
+
+impl HitResult {
+    pub fn is_hit(&self) -> bool {
+        return *self != HitResult::Miss;
+    }
+
+    // How much the note counts towards the accuracy
+    pub fn weight(&self) -> f32 {
+        match self {
+            HitResult::Perfect => 1.,
+            HitResult::Great => 2. / 3.,
+            HitResult::Good => 1. / 3.,
+            HitResult::Miss => 0.,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HitResult::Perfect => "Perfect",
+            HitResult::Great => "Great",
+            HitResult::Good => "Good",
+            HitResult::Miss => "Miss",
+        }
+    }
+}
+
+// Largest hit offsets (ms, early or late) for each tier, anything else inside the hit window is Good
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct JudgementWindows {
+    pub perfect_ms: i128,
+    pub great_ms: i128,
+}
+
+impl Default for JudgementWindows {
+    fn default() -> Self {
+        JudgementWindows {
+            perfect_ms: 50,
+            great_ms: 100,
+        }
+    }
+}
+
+impl JudgementWindows {
+    // Any windows brought back to MIN_JUDGEMENT_WINDOW <= perfect <= great <= the late hit window
+    pub fn normalized(&self) -> JudgementWindows {
+        let great_ms = self.great_ms.clamp(MIN_JUDGEMENT_WINDOW, NOTE_LATE_HIT_WINDOW);
+        return JudgementWindows {
+            perfect_ms: self.perfect_ms.clamp(MIN_JUDGEMENT_WINDOW, great_ms),
+            great_ms,
+        };
+    }
+
+    // Windows the game lets players pick
+    pub fn is_valid(&self) -> bool {
+        return self.normalized() == *self;
+    }
+
+    // No offset is a miss
+    pub fn judge(&self, offset_ms: Option<i128>) -> HitResult {
+        let Some(offset_ms) = offset_ms else {
+            return HitResult::Miss;
+        };
+        if offset_ms.abs() <= self.perfect_ms {
+            return HitResult::Perfect;
+        } else if offset_ms.abs() <= self.great_ms {
+            return HitResult::Great;
+        } else {
+            return HitResult::Good;
+        }
+    }
+}
+
+// Result of a single note, kept in the order the notes were judged for the HUD and results screen
+#[derive(Clone, Debug)]
+pub struct NoteJudgement {
+    pub hit_ms: i128,
+    // How late (positive) or early (negative) the note was hit, None if it was missed
+    pub offset_ms: Option<i128>,
+    pub result: HitResult,
+}
+
+impl NoteJudgement {
+    pub fn new(hit_ms: i128, offset_ms: Option<i128>, windows: &JudgementWindows) -> NoteJudgement {
+        NoteJudgement {
+            hit_ms,
+            offset_ms,
+            result: windows.judge(offset_ms),
+        }
+    }
+}
+
+// Notes cover a square of `size` grid cells around their (possibly off-grid) position.
+// The hitbox scale shrinks the area around the note, including the cursor hitbox.
+//
+// First time the cursor is in the note hitbox while moving in a straight line between two frames (time ms, position).
+// Only the part of the movement inside the note hit window counts, so a hit is never judged before the window opens.
+pub fn sweep_note_hit(note: &Note, from: (i128, Vec2), to: (i128, Vec2), hitbox_scale: f32) -> Option<i128> {
+    let ((from_ms, from_pos), (to_ms, to_pos)) = (from, to);
+    let window_start_ms = note.hit_ms - NOTE_EARLY_HIT_WINDOW;
+    let window_end_ms = note.hit_ms + NOTE_LATE_HIT_WINDOW;
+    if to_ms <= window_start_ms || from_ms > window_end_ms {
+        return None;
+    }
+    let start_ms = from_ms.max(window_start_ms);
+    let end_ms = to_ms.min(window_end_ms);
+    let position_at = |time_ms: i128| -> Vec2 {
+        if to_ms <= from_ms {
+            return to_pos;
+        }
+        return from_pos.lerp(to_pos, (time_ms - from_ms) as f32 / (to_ms - from_ms) as f32);
+    };
+    let (start, end) = (position_at(start_ms), position_at(end_ms));
+
+    // Clip the movement to the hitbox one axis at a time, what is left of it is inside the hitbox
+    let half_size = (note.size / 2. + CURSOR_HITBOX) * hitbox_scale;
+    let (min, max) = (Vec2::new(note.x - half_size, note.y - half_size), Vec2::new(note.x + half_size, note.y + half_size));
+    let (mut enter, mut exit) = (0_f32, 1_f32);
+    for (start, end, min, max) in [(start.x, end.x, min.x, max.x), (start.y, end.y, min.y, max.y)] {
+        let delta = end - start;
+        if delta == 0. {
+            // Not moving on this axis, it has to be inside the hitbox the whole time
+            if start <= min || start >= max {
+                return None;
+            }
+            continue;
+        }
+        let (a, b) = ((min - start) / delta, (max - start) / delta);
+        enter = enter.max(a.min(b));
+        exit = exit.min(a.max(b));
+    }
+    // Only touching the edge of the hitbox is not a hit
+    if enter >= exit {
+        return None;
+    }
+    let hit_time_ms = start_ms + ((end_ms - start_ms) as f32 * enter).round() as i128;
+    return Some(hit_time_ms.max(window_start_ms + 1).min(end_ms));
+}
@@ -0,0 +1,70 @@
+// The rules of a play, without a window, audio or assets.
+// The play state feeds a `PlaySimulation` with the cursor of every frame, replays and autoplay can be judged with one anywhere.
+pub mod judgement;
+pub mod scoring;
+pub mod health;
+pub mod tracker;
+pub mod autoplay;
+mod simulation;
+
+use crate::modifiers::Modifiers;
+
+pub use simulation::{simulate, JudgedNote, PlaySimulation};
+
+// A note of a map, the map formats are parsed into these
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Note {
+    pub hit_ms: i128,
+    pub x: f32,
+    pub y: f32,
+    pub size: f32
+}
+
+// A position on the grid, kept here so the engine doesn't need a math library
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vec2 {
+    pub const ZERO: Vec2 = Vec2 { x: 0., y: 0. };
+
+    pub fn new(x: f32, y: f32) -> Vec2 {
+        return Vec2 { x, y };
+    }
+
+    // `self` at 0, `to` at 1
+    pub fn lerp(self, to: Vec2, t: f32) -> Vec2 {
+        return Vec2::new(self.x + (to.x - self.x) * t, self.y + (to.y - self.y) * t);
+    }
+}
+
+// Position of the cursor on the grid at a game time (ms, same clock as the note hit times)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ReplayFrame {
+    pub time_ms: i32,
+    pub x: f32,
+    pub y: f32,
+}
+
+// The notes as they are played, moved by the map offset and flipped by the mirror and flip modifiers
+pub fn get_play_notes(notes: &[Note], offset_ms: i128, modifiers: &Modifiers) -> Vec<Note> {
+    let mut notes = notes.to_vec();
+    for note in &mut notes {
+        note.hit_ms += offset_ms;
+        (note.x, note.y) = modifiers.apply_mirror(note.x, note.y);
+    }
+    return notes;
+}
+
+// Play time (ms) from the time since the start of the play, on the same clock as the note hit times.
+// Negative while waiting for a first note that comes too early.
+pub fn get_play_time_ms(elapsed_ms: i128, first_note_ms: i128, start_finish_wait: i128) -> i128 {
+    // If the first note of the map is pops up too fast
+    // we will wait a little time so that it can pop up.
+    if first_note_ms <= start_finish_wait {
+        return elapsed_ms - start_finish_wait;
+    }
+    return elapsed_ms;
+}
@@ -0,0 +1,59 @@
+use crate::modifiers::Modifiers;
+
+use super::judgement::NoteJudgement;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PlayGrade {
+    SS,
+    A,
+    B,
+    C,
+    D
+}
+
+pub fn calc_play_grade(accuracy: f32) -> PlayGrade {
+    if accuracy == 100. {
+        return PlayGrade::SS;
+    } else if accuracy > 93.5 {
+        return PlayGrade::A;
+    } else if accuracy > 87.5 {
+        return PlayGrade::B;
+    } else if accuracy > 78.5 {
+        return PlayGrade::C;
+    } else {
+        return PlayGrade::D;
+    }
+}
+
+pub fn calc_score(hits: i128, max_combo: i128, accuracy: f32, modifiers: &Modifiers) -> i128 {
+    return ((hits * 5 * max_combo) as f32 * (0.8 + accuracy / 500.) * modifiers.score_multiplier()) as i128;
+}
+
+pub fn get_play_grade_name(grade: PlayGrade) -> String {
+    match grade {
+        PlayGrade::SS => {
+            "SS".to_string()
+        },
+        PlayGrade::A => {
+            "A".to_string()
+        },
+        PlayGrade::B => {
+            "B".to_string()
+        },
+        PlayGrade::C => {
+            "C".to_string()
+        },
+        PlayGrade::D => {
+            "D".to_string()
+        },
+    }
+}
+
+// Accuracy (0 - 100) of the judged notes, each tier is weighted. Nothing judged yet is 100%.
+pub fn get_accuracy_of(judgements: &[NoteJudgement]) -> f32 {
+    if judgements.is_empty() {
+        return 100.;
+    }
+    let total: f32 = judgements.iter().map(|judgement| judgement.result.weight()).sum();
+    return total / judgements.len() as f32 * 100.;
+}
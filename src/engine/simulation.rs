@@ -0,0 +1,366 @@
+
+use crate::modifiers::Modifiers;
+
+use super::{health::PlayHealth, judgement::{sweep_note_hit, HitResult, JudgementWindows, NoteJudgement, NOTE_EARLY_HIT_WINDOW, NOTE_LATE_HIT_WINDOW}, scoring::{calc_play_grade, calc_score, get_accuracy_of, PlayGrade}, tracker::MapNoteTracker, Note, ReplayFrame, Vec2};
+
+// A play of a map, judged from the cursor position of every frame.
+// Everything is in play time: the note hit times are scaled by the play speed, like the game clock.
+#[derive(Default, Clone)]
+pub struct PlaySimulation {
+    // In hit order, like the map notes
+    notes: Vec<Note>,
+    results: Vec<Option<HitResult>>,
+    // Every note before this one is judged
+    first_unjudged: usize,
+    windows: JudgementWindows,
+    modifiers: Modifiers,
+    pub judgements: Vec<NoteJudgement>,
+    pub current_combo: i128,
+    pub max_combo: i128,
+    pub hits: i128,
+    pub misses: i128,
+    pub health: PlayHealth,
+    // Play time at which the health ran out, nothing is judged after it
+    pub failed_at_ms: Option<i128>,
+    // Every frame the cursor was judged on, saved as the replay of the play
+    pub frames: Vec<ReplayFrame>,
+}
+
+// A note judged by a frame, `index` is its position in the map notes
+pub struct JudgedNote {
+    pub index: usize,
+    pub judgement: NoteJudgement,
+}
+
+impl PlaySimulation {
+    // The notes are the map notes with its offset and the mirror and flip modifiers already applied
+    pub fn new(map_notes: &[Note], modifiers: &Modifiers, windows: JudgementWindows) -> PlaySimulation {
+        let notes: Vec<Note> = map_notes.iter().map(|note| Note {
+            hit_ms: (note.hit_ms as f32 / modifiers.speed) as i128,
+            ..*note
+        }).collect();
+        PlaySimulation {
+            results: vec![None; notes.len()],
+            notes,
+            first_unjudged: 0,
+            windows,
+            modifiers: modifiers.clone(),
+            judgements: vec![],
+            current_combo: 0,
+            max_combo: 0,
+            hits: 0,
+            misses: 0,
+            health: PlayHealth::new(map_notes, modifiers.speed, !modifiers.no_fail),
+            failed_at_ms: None,
+            frames: vec![],
+        }
+    }
+
+    // Judges the notes along the cursor path since the last frame, returns the notes it judged in the order they were judged.
+    // Only one frame is kept per millisecond, later ones in the same millisecond are ignored.
+    pub fn update(&mut self, time_ms: i128, x: f32, y: f32) -> Vec<JudgedNote> {
+        let mut judged = vec![];
+        if self.failed_at_ms.is_some() || self.frames.last().is_some_and(|last| last.time_ms as i128 >= time_ms) {
+            return judged;
+        }
+        let to = (time_ms, Vec2::new(x, y));
+        let from = self.frames.last().map_or(to, |last| (last.time_ms as i128, Vec2::new(last.x, last.y)));
+        self.frames.push(ReplayFrame { time_ms: time_ms as i32, x, y });
+
+        let hitbox_scale = self.modifiers.hitbox_scale();
+        for index in self.first_unjudged..self.notes.len() {
+            let note = self.notes[index];
+            if note.hit_ms - NOTE_EARLY_HIT_WINDOW >= time_ms {
+                break;
+            }
+            if self.results[index].is_some() {
+                continue;
+            }
+            let offset_ms = match sweep_note_hit(&note, from, to, hitbox_scale) {
+                Some(hit_time_ms) => Some(hit_time_ms - note.hit_ms),
+                None if time_ms > note.hit_ms + NOTE_LATE_HIT_WINDOW => None,
+                None => continue,
+            };
+            let judgement = NoteJudgement::new(note.hit_ms, offset_ms, &self.windows);
+            self.add_judgement(index, judgement.clone());
+            judged.push(JudgedNote { index, judgement });
+        }
+        while self.results.get(self.first_unjudged).is_some_and(|result| result.is_some()) {
+            self.first_unjudged += 1;
+        }
+
+        if self.health.is_empty() {
+            self.failed_at_ms = Some(time_ms);
+        }
+        return judged;
+    }
+
+    fn add_judgement(&mut self, index: usize, judgement: NoteJudgement) {
+        self.results[index] = Some(judgement.result);
+        if judgement.result.is_hit() {
+            self.current_combo += 1;
+            self.hits += 1;
+            self.max_combo = self.max_combo.max(self.current_combo);
+            self.health.on_hit();
+        } else {
+            self.current_combo = 0;
+            self.misses += 1;
+            self.health.on_miss();
+        }
+        self.judgements.push(judgement);
+    }
+
+    pub fn windows(&self) -> JudgementWindows {
+        return self.windows;
+    }
+
+    // The next note to hit
+    pub fn next_note(&self) -> Option<&Note> {
+        return self.notes.get(self.first_unjudged..)?.iter()
+            .enumerate()
+            .find(|(offset, _)| self.results[self.first_unjudged + offset].is_none())
+            .map(|(_, note)| note);
+    }
+
+    pub fn first_note_ms(&self) -> i128 {
+        return self.notes.first().map_or(0, |note| note.hit_ms);
+    }
+
+    pub fn last_note_ms(&self) -> i128 {
+        return self.notes.last().map_or(0, |note| note.hit_ms);
+    }
+
+    // The play ends a while after the last note
+    pub fn is_finished(&self, time_ms: i128, start_finish_wait: i128) -> bool {
+        return time_ms > self.last_note_ms() + start_finish_wait;
+    }
+
+    // Shows the notes that are not judged yet
+    pub fn note_tracker(&self) -> MapNoteTracker {
+        return MapNoteTracker::new(self.notes.iter().copied().enumerate().filter(|(index, _)| self.results[*index].is_none()).collect());
+    }
+
+    // Normalized to 0 - 100
+    pub fn accuracy(&self) -> f32 {
+        return get_accuracy_of(&self.judgements);
+    }
+
+    pub fn score(&self) -> i128 {
+        return calc_score(self.hits, self.max_combo, self.accuracy(), &self.modifiers);
+    }
+
+    pub fn grade(&self) -> PlayGrade {
+        return calc_play_grade(self.accuracy());
+    }
+
+    pub fn count_judgements(&self, result: HitResult) -> usize {
+        return self.judgements.iter().filter(|judgement| judgement.result == result).count();
+    }
+}
+
+// Judges a recorded cursor like the game did, it stops where the health ran out
+pub fn simulate(notes: &[Note], modifiers: &Modifiers, windows: JudgementWindows, frames: &[ReplayFrame]) -> PlaySimulation {
+    let mut play = PlaySimulation::new(notes, modifiers, windows);
+    for frame in frames {
+        if play.failed_at_ms.is_some() {
+            break;
+        }
+        play.update(frame.time_ms as i128, frame.x, frame.y);
+    }
+    return play;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modifiers::Modifiers;
+
+    use super::{simulate, HitResult, JudgementWindows, Note, PlaySimulation, ReplayFrame};
+
+    fn note(hit_ms: i128, x: f32, y: f32) -> Note {
+        return Note { hit_ms, x, y, size: 1. };
+    }
+
+    // The cursor sits at (x, y) from `from_ms` to `to_ms`, one frame every `frame_ms`
+    fn hold(frames: &mut Vec<ReplayFrame>, from_ms: i32, to_ms: i32, frame_ms: i32, x: f32, y: f32) {
+        let mut time_ms = from_ms;
+        while time_ms <= to_ms {
+            frames.push(ReplayFrame { time_ms, x, y });
+            time_ms += frame_ms;
+        }
+    }
+
+    #[test]
+    fn cursor_on_the_note_hits_it() {
+        let mut frames = vec![];
+        hold(&mut frames, 0, 1100, 10, 0., 0.);
+        let play = simulate(&[note(1000, 0., 0.)], &Modifiers::default(), JudgementWindows::default(), &frames);
+        assert_eq!(play.judgements.len(), 1);
+        assert_eq!(play.judgements[0].result, HitResult::Perfect);
+        // The hit window opens right after the hit time
+        assert_eq!(play.judgements[0].offset_ms, Some(1));
+        assert_eq!((play.hits, play.misses, play.max_combo), (1, 0, 1));
+        assert_eq!(play.accuracy(), 100.);
+    }
+
+    #[test]
+    fn late_hits_are_judged_by_their_offset() {
+        let mut frames = vec![];
+        hold(&mut frames, 0, 1070, 10, 1., 1.);
+        hold(&mut frames, 1080, 1200, 10, 0., 0.);
+        let play = simulate(&[note(1000, 0., 0.)], &Modifiers::default(), JudgementWindows::default(), &frames);
+        assert_eq!(play.judgements[0].result, HitResult::Great);
+        assert!(play.judgements[0].offset_ms.is_some_and(|offset_ms| offset_ms > 70 && offset_ms <= 80));
+    }
+
+    #[test]
+    fn note_is_missed_after_the_late_window() {
+        let mut frames = vec![];
+        hold(&mut frames, 0, 1190, 10, 1., 1.);
+        let mut play = simulate(&[note(1000, 0., 0.)], &Modifiers::default(), JudgementWindows::default(), &frames);
+        assert!(play.judgements.is_empty());
+
+        let judged = play.update(1210, 1., 1.);
+        assert_eq!(judged.len(), 1);
+        assert_eq!(judged[0].judgement.result, HitResult::Miss);
+        assert_eq!(judged[0].judgement.offset_ms, None);
+        assert_eq!((play.hits, play.misses), (0, 1));
+        assert!(play.next_note().is_none());
+    }
+
+    #[test]
+    fn combo_is_reset_by_a_miss() {
+        let notes = [note(1000, -1., 0.), note(1500, 0., 0.), note(2000, 1., 1.), note(2500, 1., 0.)];
+        let mut frames = vec![];
+        hold(&mut frames, 0, 1000, 10, -1., 0.);
+        hold(&mut frames, 1010, 1500, 10, 0., 0.);
+        // Waits away from the third note until it is missed
+        hold(&mut frames, 1510, 2300, 10, -1., -1.);
+        hold(&mut frames, 2310, 2600, 10, 1., 0.);
+        let play = simulate(&notes, &Modifiers::default(), JudgementWindows::default(), &frames);
+        let results: Vec<HitResult> = play.judgements.iter().map(|judgement| judgement.result).collect();
+        assert_eq!(results, vec![HitResult::Perfect, HitResult::Perfect, HitResult::Miss, HitResult::Perfect]);
+        assert_eq!((play.current_combo, play.max_combo), (1, 2));
+        assert_eq!((play.hits, play.misses), (3, 1));
+    }
+
+    #[test]
+    fn missing_every_note_fails_the_play() {
+        let notes: Vec<Note> = (1..=40).map(|index| note(index * 250, 0., 0.)).collect();
+        let mut frames = vec![];
+        hold(&mut frames, 0, 11000, 10, 1., 1.);
+
+        let play = simulate(&notes, &Modifiers::default(), JudgementWindows::default(), &frames);
+        let failed_at_ms = play.failed_at_ms.expect("the play should fail");
+        assert!(play.health.is_empty());
+        assert!(play.misses < notes.len() as i128);
+        assert!(play.frames.last().is_some_and(|frame| frame.time_ms as i128 == failed_at_ms));
+
+        let no_fail = Modifiers { no_fail: true, ..Modifiers::default() };
+        let play = simulate(&notes, &no_fail, JudgementWindows::default(), &frames);
+        assert_eq!(play.failed_at_ms, None);
+        assert_eq!(play.misses, notes.len() as i128);
+    }
+
+    #[test]
+    fn failed_play_judges_nothing_more() {
+        let notes: Vec<Note> = (1..=40).map(|index| note(index * 250, 0., 0.)).collect();
+        let mut play = PlaySimulation::new(&notes, &Modifiers::default(), JudgementWindows::default());
+        let mut time_ms = 0;
+        while play.failed_at_ms.is_none() {
+            time_ms += 10;
+            play.update(time_ms, 1., 1.);
+        }
+        let judgements = play.judgements.len();
+        assert!(play.update(time_ms + 1000, 0., 0.).is_empty());
+        assert_eq!(play.judgements.len(), judgements);
+    }
+
+    #[test]
+    fn speed_scales_the_hit_times() {
+        let fast = Modifiers { speed: 2., ..Modifiers::default() };
+        let play = PlaySimulation::new(&[note(1000, 0., 0.)], &fast, JudgementWindows::default());
+        assert_eq!(play.first_note_ms(), 500);
+    }
+
+    // Cursor moving in straight lines between (time ms, x, y) waypoints, still before the first and after the last one
+    fn path_position(waypoints: &[(i128, f32, f32)], time_ms: i128) -> (f32, f32) {
+        let (first, last) = (waypoints[0], waypoints[waypoints.len() - 1]);
+        if time_ms <= first.0 {
+            return (first.1, first.2);
+        }
+        for pair in waypoints.windows(2) {
+            let ((from_ms, from_x, from_y), (to_ms, to_x, to_y)) = (pair[0], pair[1]);
+            if time_ms <= to_ms {
+                let progress = (time_ms - from_ms) as f32 / (to_ms - from_ms) as f32;
+                return (from_x + (to_x - from_x) * progress, from_y + (to_y - from_y) * progress);
+            }
+        }
+        return (last.1, last.2);
+    }
+
+    // Plays the path at a frame rate, the frame times are rounded to the millisecond like the game clock
+    fn play_at_fps(notes: &[Note], waypoints: &[(i128, f32, f32)], fps: i128, end_ms: i128) -> PlaySimulation {
+        let mut frames = vec![];
+        let mut frame = 0;
+        loop {
+            let time_ms = (frame * 1000 + fps / 2) / fps;
+            if time_ms > end_ms {
+                break;
+            }
+            let (x, y) = path_position(waypoints, time_ms);
+            frames.push(ReplayFrame { time_ms: time_ms as i32, x, y });
+            frame += 1;
+        }
+        return simulate(notes, &Modifiers::default(), JudgementWindows::default(), &frames);
+    }
+
+    // The waypoints are on multiples of 100ms, where both frame rates have a frame, so both see the same path
+    fn assert_same_at_30_and_500_fps(notes: &[Note], waypoints: &[(i128, f32, f32)]) -> PlaySimulation {
+        let end_ms = notes.last().unwrap().hit_ms + 400;
+        let slow = play_at_fps(notes, waypoints, 30, end_ms);
+        let fast = play_at_fps(notes, waypoints, 500, end_ms);
+        let judgements = |play: &PlaySimulation| -> Vec<(i128, Option<i128>, HitResult)> {
+            return play.judgements.iter().map(|judgement| (judgement.hit_ms, judgement.offset_ms, judgement.result)).collect();
+        };
+        assert_eq!(judgements(&slow), judgements(&fast));
+        assert_eq!(slow.judgements.len(), notes.len());
+        assert_eq!((slow.hits, slow.misses, slow.max_combo), (fast.hits, fast.misses, fast.max_combo));
+        assert_eq!(slow.accuracy(), fast.accuracy());
+        assert_eq!(slow.score(), fast.score());
+        return slow;
+    }
+
+    #[test]
+    fn swipe_between_two_frames_hits_at_any_frame_rate() {
+        // Crosses the note between 1039ms and 1061ms, at 30 FPS the frames around it are at 1033ms and 1067ms, both off the note
+        let play = assert_same_at_30_and_500_fps(&[note(1000, 0., 0.)], &[(1000, -3., 0.), (1100, 3., 0.)]);
+        assert_eq!(play.judgements[0].offset_ms, Some(39));
+        assert_eq!(play.judgements[0].result, HitResult::Perfect);
+    }
+
+    #[test]
+    fn cursor_waiting_on_the_note_hits_when_the_window_opens() {
+        let play = assert_same_at_30_and_500_fps(&[note(1000, 0., 0.)], &[(800, 2., 2.), (900, 0., 0.)]);
+        assert_eq!(play.judgements[0].offset_ms, Some(1));
+    }
+
+    #[test]
+    fn frame_straddling_the_window_close_only_hits_before_it() {
+        // The window closes at 1210ms, between the 30 FPS frames at 1200ms and 1233ms
+        let play = assert_same_at_30_and_500_fps(&[note(1010, 0., 0.)], &[(1200, -0.8, 0.), (1300, 1.2, 0.)]);
+        assert_eq!(play.judgements[0].offset_ms, Some(198));
+        assert_eq!(play.judgements[0].result, HitResult::Good);
+
+        // Reaches the note 2ms after the window closed
+        let play = assert_same_at_30_and_500_fps(&[note(1010, 0., 0.)], &[(1200, -0.87, 0.), (1300, 1.13, 0.)]);
+        assert_eq!(play.judgements[0].result, HitResult::Miss);
+    }
+
+    #[test]
+    fn frame_rate_does_not_change_a_whole_play() {
+        let notes = [note(500, -1., -1.), note(700, 1., -1.), note(900, 1., 1.), note(1300, 0., 0.), note(1500, -1., 1.)];
+        let waypoints = [(400, 0., 0.), (500, -1.5, -1.5), (600, -1., -1.), (700, 1.4, -0.6), (800, 1., 0.), (900, 1., 1.2), (1300, 0., 0.), (1400, -1., 0.), (1500, -1., 1.)];
+        let play = assert_same_at_30_and_500_fps(&notes, &waypoints);
+        assert_eq!(play.hits, notes.len() as i128);
+    }
+}
@@ -0,0 +1,30 @@
+use super::Note;
+
+// Tracks which notes should be added next, each with its index in the `PlaySimulation`
+#[derive(Default)]
+pub struct MapNoteTracker {
+    notes: Vec<(usize, Note)>,
+}
+
+impl MapNoteTracker {
+    // The notes are in play time, like the simulation notes
+    pub fn new(notes: Vec<(usize, Note)>) -> Self {
+        return Self {
+            notes
+        };
+    }
+
+    pub fn update_get_next(&mut self, time_ms: i128, approach_time_ms: i128) -> Option<Vec<(usize, Note)>> {
+        if self.notes.is_empty() {
+            return None;
+        }
+        let (notes, waiting): (Vec<_>, Vec<_>) = self.notes.drain(..)
+            .partition(|(_, note)| note.hit_ms - approach_time_ms < time_ms);
+        self.notes = waiting;
+        return Some(notes);
+    }
+
+    pub fn has_more_notes(&self) -> bool {
+        return !self.notes.is_empty();
+    }
+}
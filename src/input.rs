@@ -0,0 +1,91 @@
+use bevy::{ecs::system::{Res, SystemParam}, input::{gamepad::{GamepadButton, GamepadButtonType}, keyboard::KeyCode, ButtonInput}};
+
+// Everything the menus react to, each with its keys and controller buttons
+#[derive(Clone, Copy, PartialEq)]
+pub enum MenuAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    First,
+    Last,
+    Confirm,
+    Back,
+    Retry,
+    Pause,
+    // Song select
+    OpenSettings,
+    ToggleModifier,
+    SpeedDown,
+    SpeedUp,
+    // Watching replays
+    Watch,
+    ReplayPause,
+    ReplayTrail
+}
+
+impl MenuAction {
+    fn keys(&self) -> &'static [KeyCode] {
+        match self {
+            MenuAction::Up => &[KeyCode::ArrowUp],
+            MenuAction::Down => &[KeyCode::ArrowDown],
+            MenuAction::Left => &[KeyCode::ArrowLeft],
+            MenuAction::Right => &[KeyCode::ArrowRight],
+            MenuAction::PageUp => &[KeyCode::PageUp],
+            MenuAction::PageDown => &[KeyCode::PageDown],
+            MenuAction::First => &[KeyCode::Home],
+            MenuAction::Last => &[KeyCode::End],
+            MenuAction::Confirm => &[KeyCode::Enter, KeyCode::NumpadEnter],
+            MenuAction::Back => &[KeyCode::Escape],
+            MenuAction::Retry => &[KeyCode::KeyR],
+            MenuAction::Pause => &[KeyCode::Escape],
+            MenuAction::OpenSettings => &[KeyCode::KeyO],
+            MenuAction::ToggleModifier => &[KeyCode::Space],
+            MenuAction::SpeedDown => &[KeyCode::Minus, KeyCode::NumpadSubtract],
+            MenuAction::SpeedUp => &[KeyCode::Equal, KeyCode::NumpadAdd],
+            MenuAction::Watch => &[KeyCode::KeyW],
+            MenuAction::ReplayPause => &[KeyCode::Space],
+            MenuAction::ReplayTrail => &[KeyCode::KeyT],
+        }
+    }
+
+    fn buttons(&self) -> &'static [GamepadButtonType] {
+        match self {
+            MenuAction::Up => &[GamepadButtonType::DPadUp],
+            MenuAction::Down => &[GamepadButtonType::DPadDown],
+            MenuAction::Left => &[GamepadButtonType::DPadLeft],
+            MenuAction::Right => &[GamepadButtonType::DPadRight],
+            MenuAction::PageUp => &[GamepadButtonType::LeftTrigger],
+            MenuAction::PageDown => &[GamepadButtonType::RightTrigger],
+            MenuAction::First => &[GamepadButtonType::LeftTrigger2],
+            MenuAction::Last => &[GamepadButtonType::RightTrigger2],
+            MenuAction::Confirm => &[GamepadButtonType::South],
+            MenuAction::Back => &[GamepadButtonType::East],
+            MenuAction::Retry => &[GamepadButtonType::North],
+            MenuAction::Pause => &[GamepadButtonType::Start],
+            MenuAction::OpenSettings => &[GamepadButtonType::Select],
+            MenuAction::ToggleModifier => &[GamepadButtonType::North],
+            MenuAction::SpeedDown => &[GamepadButtonType::LeftThumb],
+            MenuAction::SpeedUp => &[GamepadButtonType::RightThumb],
+            MenuAction::Watch => &[GamepadButtonType::West],
+            MenuAction::ReplayPause => &[GamepadButtonType::South],
+            MenuAction::ReplayTrail => &[GamepadButtonType::West],
+        }
+    }
+}
+
+// Keyboard and controller input for the menus, any connected controller can be used.
+#[derive(SystemParam)]
+pub struct MenuInput<'w> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    buttons: Res<'w, ButtonInput<GamepadButton>>,
+}
+
+impl<'w> MenuInput<'w> {
+    pub fn just_pressed(&self, action: MenuAction) -> bool {
+        return self.keys.any_just_pressed(action.keys().iter().copied())
+            || self.buttons.get_just_pressed().any(|button| action.buttons().contains(&button.button_type));
+    }
+}
@@ -0,0 +1,28 @@
+// The game, shared by the game binary and the command line tool (src/bin/soundaim-cli).
+// Everything that needs a window or audio is behind the "client" feature, the rest runs headless.
+pub mod map;
+pub mod modifiers;
+pub mod scores;
+pub mod replay;
+pub mod engine;
+mod storage;
+#[cfg(feature = "client")]
+pub mod state;
+#[cfg(feature = "client")]
+mod menu;
+#[cfg(feature = "client")]
+mod startup;
+#[cfg(feature = "client")]
+mod loading;
+#[cfg(feature = "client")]
+mod input;
+#[cfg(feature = "client")]
+pub mod library;
+#[cfg(feature = "client")]
+pub mod settings;
+#[cfg(feature = "client")]
+mod play;
+#[cfg(feature = "client")]
+mod results;
+#[cfg(feature = "client")]
+pub mod debug;
\ No newline at end of file
@@ -0,0 +1,232 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use bevy::{app::{App, Plugin, Update}, asset::{AssetEvent, AssetServer, Assets, Handle}, ecs::{event::EventReader, system::{Res, ResMut, Resource}}, log::{info, warn}, render::texture::Image};
+
+use crate::{map::{split_map_name, Map, MapMetadata, NoteData, METADATA_FILE}, menu::song_select::SongSelection};
+
+const NOTE_EXTENSIONS: [&str; 1] = ["txt"];
+const AUDIO_EXTENSIONS: [&str; 3] = ["mp3", "ogg", "wav"];
+// Packages holding the notes, audio and metadata in a single file
+const PACKAGE_EXTENSIONS: [&str; 1] = ["sspm"];
+
+pub struct LibraryPlugin;
+
+impl Plugin for LibraryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, apply_map_metadata);
+    }
+}
+
+// Every map found in the maps directory, sorted by title.
+// Titles from the metadata only show up once it is loaded, the maps are sorted again then.
+#[derive(Resource, Default)]
+pub struct MapLibrary {
+    pub maps: Vec<Map>,
+}
+
+impl MapLibrary {
+    // Scans the maps directory (relative to the assets folder) and starts loading the note data of every map found.
+    // A map is either a notes/audio pair sharing the same file name, a single package file (.sspm),
+    // or a folder containing one of those. Folders can also have a map.json file with the map metadata.
+    pub fn scan(server: &AssetServer, maps_path: &str) -> MapLibrary {
+        let maps_path = maps_path.trim_matches('/');
+        let mut library = MapLibrary::default();
+        let Some(maps_dir) = assets_dir().map(|dir| dir.join(maps_path)) else {
+            warn!("Map scanning is not supported on this platform");
+            return library;
+        };
+        let entries = match read_dir_sorted(&maps_dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("Could not read the maps directory {0}: {1}", maps_dir.display(), err);
+                return library;
+            }
+        };
+
+        for entry in &entries {
+            if entry.is_dir() {
+                let Ok(package) = read_dir_sorted(entry) else {
+                    continue;
+                };
+                let name = file_name(entry);
+                let package_path = maps_path.to_owned() + "/" + &name;
+                if let Some(package_file) = package.iter().find(|path| has_extension(path, &PACKAGE_EXTENSIONS)) {
+                    library.maps.push(build_package_map(server, &name, &package_path, &file_name(package_file)));
+                    continue;
+                }
+                let Some(notes) = package.iter().find(|path| has_extension(path, &NOTE_EXTENSIONS)) else {
+                    continue;
+                };
+                let metadata = package.iter()
+                    .find(|path| path.is_file() && file_name(path) == METADATA_FILE)
+                    .map(|path| server.load::<MapMetadata>(package_path.clone() + "/" + &file_name(path)));
+                let audio = package.iter().find(|path| has_extension(path, &AUDIO_EXTENSIONS));
+                // The audio file can also come from the metadata
+                if audio.is_none() && metadata.is_none() {
+                    warn!("Skipping map package {0}, it has no audio file", entry.display());
+                    continue;
+                }
+                let mut map = build_map(server, &name, &package_path, &file_name(notes), &audio.map_or(String::new(), |audio| file_name(audio)));
+                if audio.is_none() {
+                    map.audio_path = String::new();
+                }
+                map.metadata = metadata;
+                library.maps.push(map);
+            } else if has_extension(entry, &PACKAGE_EXTENSIONS) {
+                let name = entry.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                library.maps.push(build_package_map(server, &name, maps_path, &file_name(entry)));
+            } else if has_extension(entry, &NOTE_EXTENSIONS) {
+                let Some(audio) = entries.iter().find(|path| has_extension(path, &AUDIO_EXTENSIONS) && path.file_stem() == entry.file_stem()) else {
+                    warn!("Skipping map {0}, it has no matching audio file", entry.display());
+                    continue;
+                };
+                let name = entry.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                library.maps.push(build_map(server, &name, maps_path, &file_name(entry), &file_name(audio)));
+            }
+        }
+
+        library.sort(0);
+        info!("Found {0} maps in {1}", library.maps.len(), maps_dir.display());
+        return library;
+    }
+
+    // Sorts the maps by title, returns the new index of the map at `index`
+    pub fn sort(&mut self, index: usize) -> usize {
+        let notes = self.maps.get(index).map(|map| map.notes.id());
+        self.maps.sort_by_key(|map| map.title.to_lowercase());
+        return notes.and_then(|notes| self.maps.iter().position(|map| map.notes.id() == notes)).unwrap_or(0);
+    }
+}
+
+fn build_map(server: &AssetServer, name: &str, dir: &str, notes_file: &str, audio_file: &str) -> Map {
+    let (artist, title) = split_map_name(name);
+    Map {
+        title,
+        artist,
+        mapper: "Unknown".to_owned(),
+        difficulty: "N/A".to_owned(),
+        notes: server.load::<NoteData>(dir.to_owned() + "/" + notes_file),
+        audio_path: dir.to_owned() + "/" + audio_file,
+        offset_ms: 0,
+        preview_ms: None,
+        cover: None,
+        metadata: None,
+    }
+}
+
+// The name is only used until the metadata inside of the package is loaded.
+fn build_package_map(server: &AssetServer, name: &str, dir: &str, package_file: &str) -> Map {
+    let (artist, title) = split_map_name(name);
+    let path = dir.to_owned() + "/" + package_file;
+    Map {
+        title,
+        artist,
+        mapper: "Unknown".to_owned(),
+        difficulty: "N/A".to_owned(),
+        notes: server.load::<NoteData>(path.clone()),
+        audio_path: path.clone() + "#audio",
+        offset_ms: 0,
+        preview_ms: None,
+        cover: None,
+        metadata: Some(server.load::<MapMetadata>(path + "#metadata")),
+    }
+}
+
+// Fills the maps with their metadata as soon as it is loaded.
+fn apply_map_metadata(
+    mut events: EventReader<AssetEvent<MapMetadata>>,
+    metadatas: Res<Assets<MapMetadata>>,
+    server: Res<AssetServer>,
+    library: Option<ResMut<MapLibrary>>,
+    selection: Option<ResMut<SongSelection>>
+) {
+    let Some(mut library) = library else {
+        return;
+    };
+    let mut applied = false;
+    for ev in events.read() {
+        let (AssetEvent::Added { id } | AssetEvent::Modified { id }) = ev else {
+            continue;
+        };
+        let Some(metadata) = metadatas.get(*id) else {
+            continue;
+        };
+        for map in library.maps.iter_mut() {
+            let Some(handle) = map.metadata.clone().filter(|handle| handle.id() == *id) else {
+                continue;
+            };
+            apply_metadata(map, metadata, &handle, &server);
+            applied = true;
+        }
+    }
+    if !applied {
+        return;
+    }
+
+    // The selection follows its map to where it is sorted
+    let selected = selection.as_ref().map_or(0, |selection| selection.selected);
+    let selected = library.sort(selected);
+    if let Some(mut selection) = selection {
+        if selection.selected != selected {
+            selection.selected = selected;
+        }
+    }
+}
+
+pub (crate) fn apply_metadata(map: &mut Map, metadata: &MapMetadata, handle: &Handle<MapMetadata>, server: &AssetServer) {
+    for (value, field) in [
+        (&metadata.title, &mut map.title),
+        (&metadata.artist, &mut map.artist),
+        (&metadata.mapper, &mut map.mapper),
+        (&metadata.difficulty, &mut map.difficulty),
+    ] {
+        if !value.is_empty() {
+            *field = value.clone();
+        }
+    }
+    map.offset_ms = metadata.offset;
+    if metadata.preview_time.is_some() {
+        map.preview_ms = metadata.preview_time;
+    }
+
+    // Paths in the metadata are relative to the folder of the metadata file
+    let resolve = |path: &str| handle.path().and_then(|metadata_path| metadata_path.resolve_embed(path).ok());
+    if let Some(audio) = metadata.audio.as_deref().and_then(resolve) {
+        map.audio_path = audio.to_string();
+    }
+    if let Some(background) = metadata.background.as_deref().and_then(resolve) {
+        map.cover = Some(server.load::<Image>(background));
+    }
+    if metadata.cover.is_some() {
+        map.cover = metadata.cover.clone();
+    }
+}
+
+fn read_dir_sorted(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+    return Ok(paths);
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.is_file() && path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.contains(&ext.to_lowercase().as_str()))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn assets_dir() -> Option<PathBuf> {
+    Some(bevy::asset::io::file::FileAssetReader::get_base_path().join("assets"))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn assets_dir() -> Option<PathBuf> {
+    None
+}
@@ -0,0 +1,216 @@
+use bevy::{app::{App, AppExit, Plugin, Update}, asset::{Asset, AssetServer, Handle, LoadState, UntypedHandle}, core_pipeline::core_2d::Camera2dBundle, ecs::{component::Component, entity::Entity, event::EventWriter, query::{With, Without}, schedule::{common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit}, system::{Commands, Query, Res, ResMut, Resource}}, hierarchy::{BuildChildren, DespawnRecursiveExt}, log::error, render::{camera::{Camera, ClearColorConfig}, color::Color}, text::{Text, TextStyle}, ui::{node_bundles::{NodeBundle, TextBundle}, AlignItems, BackgroundColor, FlexDirection, JustifyContent, Style, UiRect, Val}, utils::default};
+
+use crate::{input::{MenuAction, MenuInput}, state::GameState};
+
+pub struct LoadingPlugin;
+
+#[derive(Component)]
+pub struct OnLoading;
+
+#[derive(Component)]
+pub struct LoadingText;
+
+#[derive(Component)]
+pub struct LoadingProgressBar;
+
+// Everything that has to be loaded before moving on to the next state.
+// This should be inserted right before entering GameState::Loading.
+#[derive(Resource)]
+pub struct LoadingTracker {
+    title: String,
+    handles: Vec<(String, UntypedHandle)>,
+    // Work done by other systems while loading, with whether it is done
+    jobs: Vec<(String, bool)>,
+    // A job that could not be done, with the reason
+    failed: Option<(String, String)>,
+    next: GameState,
+    // State to go back to if something fails to load, the game can only be quit when there is none.
+    fallback: Option<GameState>,
+    error: Option<String>,
+}
+
+impl LoadingTracker {
+    pub fn new(title: String, next: GameState, fallback: Option<GameState>) -> LoadingTracker {
+        LoadingTracker {
+            title,
+            handles: vec![],
+            jobs: vec![],
+            failed: None,
+            next,
+            fallback,
+            error: None,
+        }
+    }
+
+    pub fn track<A: Asset>(mut self, name: &str, handle: &Handle<A>) -> LoadingTracker {
+        self.add(name, handle);
+        return self;
+    }
+
+    // Waits for a job until it is finished by the system doing it
+    pub fn wait_for(mut self, name: &str) -> LoadingTracker {
+        self.jobs.push((name.to_owned(), false));
+        return self;
+    }
+
+    // Tracks an asset that only starts loading once something else is loaded
+    pub fn add<A: Asset>(&mut self, name: &str, handle: &Handle<A>) {
+        self.handles.push((name.to_owned(), handle.clone().untyped()));
+    }
+
+    pub fn finish(&mut self, name: &str) {
+        for (job, done) in self.jobs.iter_mut() {
+            if job == name {
+                *done = true;
+            }
+        }
+    }
+
+    pub fn fail(&mut self, name: &str, reason: &str) {
+        self.failed = Some((name.to_owned(), reason.to_owned()));
+    }
+}
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Loading), build_loading);
+        app.add_systems(OnExit(GameState::Loading), cleanup_loading);
+        app.add_systems(Update, (poll_loading, on_loading_error_keys).chain().run_if(in_state(GameState::Loading)));
+    }
+}
+
+fn build_loading(tracker: Res<LoadingTracker>, mut commands: Commands) {
+    commands.spawn((NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        ..default()
+    }, OnLoading)).with_children(|builder| {
+        builder.spawn((TextBundle {
+            style: Style {
+                margin: UiRect::px(10., 10., 10., 10.),
+                ..default()
+            },
+            text: Text::from_section(tracker.title.clone(), TextStyle {
+                font_size: 30.,
+                color: Color::rgb(1., 1., 1.),
+                ..default()
+            }),
+            ..default()
+        }, LoadingText));
+        builder.spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(400.),
+                height: Val::Px(12.),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgb(0.1, 0.1, 0.1)),
+            ..default()
+        }).with_children(|builder| {
+            builder.spawn((NodeBundle {
+                style: Style {
+                    width: Val::Percent(0.),
+                    height: Val::Percent(100.),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgb(0.5, 0.05, 0.7)),
+                ..default()
+            }, LoadingProgressBar));
+        });
+    });
+
+    commands.spawn((Camera2dBundle {
+        camera: Camera {
+            clear_color: ClearColorConfig::Custom(Color::rgb(0., 0., 0.)),
+            ..default()
+        },
+        ..default()
+    }, OnLoading));
+}
+
+fn poll_loading(
+    server: Res<AssetServer>,
+    mut tracker: ResMut<LoadingTracker>,
+    mut q_text: Query<&mut Text, With<LoadingText>>,
+    mut q_bar: Query<&mut Style, (With<LoadingProgressBar>, Without<LoadingText>)>,
+    mut state: ResMut<NextState<GameState>>
+) {
+    if tracker.error.is_some() {
+        return;
+    }
+    if let Some((name, reason)) = tracker.failed.clone() {
+        error!("Failed to load {0} ({1})", name, reason);
+        show_error(&mut tracker, &mut q_text, &name, &reason);
+        return;
+    }
+
+    let mut loaded = tracker.jobs.iter().filter(|(_, done)| *done).count();
+    for (name, handle) in tracker.handles.clone() {
+        match server.load_state(handle.id()) {
+            LoadState::Loaded => loaded += 1,
+            LoadState::Failed => {
+                let path = handle.path().map_or("unknown path".to_owned(), |path| path.to_string());
+                error!("Failed to load {0} ({1})", name, path);
+                show_error(&mut tracker, &mut q_text, &name, &path);
+                return;
+            }
+            _ => {}
+        }
+    }
+
+    let total = tracker.handles.len() + tracker.jobs.len();
+    if let Ok(mut bar) = q_bar.get_single_mut() {
+        let progress = if total == 0 {
+            1.
+        } else {
+            loaded as f32 / total as f32
+        };
+        bar.width = Val::Percent(progress * 100.);
+    }
+
+    if loaded == total {
+        state.set(tracker.next);
+    }
+}
+
+fn show_error(tracker: &mut LoadingTracker, q_text: &mut Query<&mut Text, With<LoadingText>>, name: &str, detail: &str) {
+    let hint = match tracker.fallback {
+        Some(_) => "Press Escape to go back",
+        None => "Press Escape to quit",
+    };
+    let error = format!("Could not load the {0}\n{1}\n\n{2}", name, detail, hint);
+    if let Ok(mut text) = q_text.get_single_mut() {
+        text.sections[0].value = error.clone();
+        text.sections[0].style.color = Color::rgb(0.9, 0.2, 0.2);
+    }
+    tracker.error = Some(error);
+}
+
+fn on_loading_error_keys(
+    tracker: Res<LoadingTracker>,
+    input: MenuInput,
+    mut state: ResMut<NextState<GameState>>,
+    mut exit: EventWriter<AppExit>
+) {
+    if tracker.error.is_none() || !(input.just_pressed(MenuAction::Back) || input.just_pressed(MenuAction::Confirm)) {
+        return;
+    }
+    match tracker.fallback {
+        Some(fallback) => state.set(fallback),
+        None => {
+            exit.send(AppExit);
+        }
+    }
+}
+
+fn cleanup_loading(mut commands: Commands, query: Query<Entity, With<OnLoading>>) {
+    for ent in query.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    commands.remove_resource::<LoadingTracker>();
+}
@@ -1,17 +1,9 @@
 use bevy::{app::App, asset::{AssetApp, AssetMetaCheck}, diagnostic::FrameTimeDiagnosticsPlugin, DefaultPlugins};
+use bevy_common_assets::json::JsonAssetPlugin;
 use bevy_kira_audio::AudioPlugin;
 use bevy_mod_billboard::plugin::BillboardPlugin;
 use bevy_obj::ObjPlugin;
-use debug::GameDebugPlugin;
-use map::{NoteData, V1NoteDataLoader};
-use state::StatePlugin;
-
-mod state;
-mod menu;
-mod startup;
-mod map;
-mod play;
-mod debug;
+use soundaim::{debug::GameDebugPlugin, library::LibraryPlugin, map::{sspm::SspmLoader, v1::V1NoteDataLoader, MapMetadata, NoteData}, state::StatePlugin};
 
 fn main() {
     App::new()
@@ -24,8 +16,11 @@ fn main() {
             FrameTimeDiagnosticsPlugin,
             GameDebugPlugin,
             BillboardPlugin,
+            LibraryPlugin,
+            JsonAssetPlugin::<MapMetadata>::new(&["map.json"]),
         ))
         .init_asset::<NoteData>()
         .init_asset_loader::<V1NoteDataLoader>()
+        .init_asset_loader::<SspmLoader>()
         .run();
 }
\ No newline at end of file
@@ -0,0 +1,142 @@
+use std::{fs, path::Path};
+
+use bevy::{asset::Asset, reflect::TypePath};
+#[cfg(feature = "client")]
+use bevy::{asset::Handle, render::texture::Image};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use self::{sspm::{parse_sspm, SspmLoaderError}, v1::{parse_v1_notes, V1NoteDataLoaderError}};
+
+pub mod v1;
+pub mod sspm;
+
+// Metadata file of a map folder
+pub const METADATA_FILE: &str = "map.json";
+// The grid goes from -GRID_HALF_SIZE to GRID_HALF_SIZE on both axes, note positions are the centers of its cells (-1, 0 and 1)
+pub const GRID_HALF_SIZE: f32 = 1.5;
+
+pub use crate::engine::Note;
+
+#[cfg(feature = "client")]
+#[derive(Clone, Default)]
+pub struct Map {
+    pub title: String,
+    pub artist: String,
+    pub mapper: String,
+    pub difficulty: String,
+    pub notes: Handle<NoteData>,
+    // Asset path of the song, it is only loaded once the map is played.
+    pub audio_path: String,
+    // Added to the hit time of every note
+    pub offset_ms: i128,
+    // Where the song preview starts in the song select
+    pub preview_ms: Option<i128>,
+    // Cover or background image
+    pub cover: Option<Handle<Image>>,
+    // Metadata that is only known once loaded (map.json or SSPM), applied to the map by the library.
+    pub metadata: Option<Handle<MapMetadata>>
+}
+
+#[derive(Asset, TypePath, Default, Clone)]
+pub struct NoteData(pub Vec<Note>);
+
+impl NoteData {
+    // The time of the last note, notes are stored in hit order.
+    pub fn length_ms(&self) -> i128 {
+        return self.0.last().map_or(0, |note| note.hit_ms);
+    }
+}
+
+// A map time as "m:ss"
+pub fn format_length(length_ms: i128) -> String {
+    let seconds = length_ms.max(0) / 1000;
+    return format!("{0}:{1:02}", seconds / 60, seconds % 60);
+}
+
+// Map information that isn't part of the notes, either from a map.json file in the map folder or embedded in the map file.
+// Empty fields are left as they are on the map.
+#[derive(Asset, TypePath, Deserialize, Serialize, Default, Clone)]
+#[serde(default)]
+pub struct MapMetadata {
+    pub title: String,
+    pub artist: String,
+    pub mapper: String,
+    #[serde(rename = "difficulty_name")]
+    pub difficulty: String,
+    // Paths are relative to the map folder
+    pub audio: Option<String>,
+    pub background: Option<String>,
+    pub offset: i128,
+    pub preview_time: Option<i128>,
+    // Image embedded in the map file
+    #[cfg(feature = "client")]
+    #[serde(skip)]
+    pub cover: Option<Handle<Image>>,
+}
+
+// Map names are usually in the "artist - title" form, with spaces replaced by underscores in the old archives.
+pub fn split_map_name(name: &str) -> (String, String) {
+    let name = name.replace('_', " ");
+    match name.split_once(" - ") {
+        Some((artist, title)) => (artist.trim().to_owned(), title.trim().to_owned()),
+        None => ("Unknown".to_owned(), name.trim().to_owned()),
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum MapFileError {
+    #[error("Could not read map: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    V1(#[from] V1NoteDataLoaderError),
+    #[error("{0}")]
+    Sspm(#[from] SspmLoaderError),
+    #[error("Invalid map.json: {0}")]
+    InvalidMetadata(#[from] serde_json::Error),
+    #[error("Unknown map format {0:?}")]
+    UnknownFormat(String)
+}
+
+// A map read straight from its file, for the tools that run without the asset server
+pub struct MapFile {
+    pub notes: NoteData,
+    pub metadata: MapMetadata,
+    // Song and cover embedded in the map file, as they are stored
+    pub audio: Option<Vec<u8>>,
+    pub cover: Option<Vec<u8>>,
+}
+
+// Parses the map with the same parsers as the asset loaders, picked by the file extension.
+// A notes file also gets the map.json next to it, like in a map folder.
+pub fn read_map_file(path: &Path) -> Result<MapFile, MapFileError> {
+    let bytes = fs::read(path)?;
+    let extension = path.extension().map_or(String::new(), |extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_str() {
+        "txt" => {
+            let metadata_path = path.with_file_name(METADATA_FILE);
+            let metadata = if metadata_path.is_file() {
+                serde_json::from_slice(&fs::read(metadata_path)?)?
+            } else {
+                MapMetadata::default()
+            };
+            return Ok(MapFile {
+                notes: parse_v1_notes(&bytes)?,
+                metadata,
+                audio: None,
+                cover: None,
+            });
+        }
+        "sspm" => {
+            let map = parse_sspm(&bytes)?;
+            return Ok(MapFile {
+                metadata: map.metadata(),
+                notes: map.notes,
+                audio: map.audio,
+                cover: map.cover,
+            });
+        }
+        _ => return Err(MapFileError::UnknownFormat(extension)),
+    }
+}
@@ -0,0 +1,850 @@
+#[cfg(feature = "client")]
+use std::io::Cursor;
+
+#[cfg(feature = "client")]
+use bevy::{asset::{AssetLoader, AsyncReadExt, LoadContext}, render::{render_asset::RenderAssetUsages, texture::{CompressedImageFormats, Image, ImageSampler, ImageType}}, utils::BoxedFuture};
+#[cfg(feature = "client")]
+use bevy_kira_audio::AudioSource;
+#[cfg(feature = "client")]
+use kira::sound::static_sound::{StaticSoundData, StaticSoundSettings};
+use sha1::{Digest, Sha1};
+use thiserror::Error;
+
+use super::{split_map_name, MapMetadata, Note, NoteData};
+
+const SIGNATURE: &[u8; 4] = b"SS+m";
+const NOTE_MARKER: &str = "ssp_note";
+const DIFFICULTY_NAME_FIELD: &str = "difficulty_name";
+
+// Loads Sound Space Plus maps (.sspm), both the v1 and v2 binary formats.
+// The notes are the root asset, the metadata, audio and cover are added as the "metadata", "audio" and "cover" labeled assets.
+#[cfg(feature = "client")]
+#[derive(Default)]
+pub struct SspmLoader;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum SspmLoaderError {
+    #[error("Could not read map: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Not a SSPM file")]
+    InvalidSignature,
+    #[error("Unsupported SSPM version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Unexpected end of file (byte {offset})")]
+    UnexpectedEof { offset: usize },
+    #[error("Invalid UTF-8 string (byte {offset})")]
+    InvalidUtf8 { offset: usize },
+    #[error("Unknown value type {value_type:#04x} (byte {offset})")]
+    UnknownValueType { value_type: u8, offset: usize },
+    #[error("Unknown marker type {marker} (byte {offset})")]
+    UnknownMarker { marker: u8, offset: usize },
+    #[error("Map has no notes")]
+    NoNotes,
+    #[error("Could not decode the embedded audio: {0}")]
+    InvalidAudio(String),
+    #[error("Could not decode the embedded cover: {0}")]
+    InvalidCover(String)
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum SspmWriterError {
+    #[error("Note {note} has size {size}, SSPM notes can't be resized")]
+    UnsupportedSize { note: usize, size: f32 },
+    #[error("Note {note} has hit time {hit_ms}ms, SSPM times go from 0 to {max}ms", max = u32::MAX)]
+    InvalidHitTime { note: usize, hit_ms: i128 },
+    #[error("The {field} is too long")]
+    StringTooLong { field: &'static str }
+}
+
+// Everything a SSPM file contains, before it is turned into assets.
+pub struct SspmMap {
+    pub id: String,
+    pub name: String,
+    pub song_name: String,
+    pub mappers: Vec<String>,
+    pub difficulty: u8,
+    pub difficulty_name: Option<String>,
+    pub notes: NoteData,
+    pub audio: Option<Vec<u8>>,
+    pub cover: Option<Vec<u8>>
+}
+
+impl SspmMap {
+    pub fn difficulty_name(&self) -> String {
+        if let Some(name) = &self.difficulty_name {
+            return name.clone();
+        }
+        return match self.difficulty {
+            1 => "Easy",
+            2 => "Medium",
+            3 => "Hard",
+            4 => "Logic",
+            5 => "Tasukete",
+            _ => "N/A",
+        }.to_owned();
+    }
+
+    pub fn metadata(&self) -> MapMetadata {
+        let (artist, title) = if self.song_name.is_empty() {
+            split_map_name(&self.name)
+        } else {
+            split_map_name(&self.song_name)
+        };
+        MapMetadata {
+            title,
+            artist,
+            mapper: self.mappers.join(", "),
+            difficulty: self.difficulty_name(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+impl AssetLoader for SspmLoader {
+    type Asset = NoteData;
+
+    type Settings = ();
+
+    type Error = SspmLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<NoteData, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let map = parse_sspm(&bytes)?;
+            let mut metadata = map.metadata();
+
+            if let Some(cover) = map.cover {
+                let image = Image::from_buffer(
+                    &cover,
+                    ImageType::Extension("png"),
+                    CompressedImageFormats::NONE,
+                    true,
+                    ImageSampler::Default,
+                    RenderAssetUsages::default()
+                ).map_err(|err| SspmLoaderError::InvalidCover(err.to_string()))?;
+                metadata.cover = Some(load_context.add_labeled_asset("cover".to_owned(), image));
+            }
+            if let Some(audio) = map.audio {
+                let sound = StaticSoundData::from_cursor(Cursor::new(audio), StaticSoundSettings::default())
+                    .map_err(|err| SspmLoaderError::InvalidAudio(err.to_string()))?;
+                load_context.add_labeled_asset("audio".to_owned(), AudioSource { sound });
+            }
+            load_context.add_labeled_asset("metadata".to_owned(), metadata);
+
+            Ok(map.notes)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sspm"]
+    }
+}
+
+pub fn parse_sspm(bytes: &[u8]) -> Result<SspmMap, SspmLoaderError> {
+    let mut reader = SspmReader::new(bytes);
+    if reader.bytes(4)? != SIGNATURE {
+        return Err(SspmLoaderError::InvalidSignature);
+    }
+    let map = match reader.u16()? {
+        1 => parse_v1(&mut reader)?,
+        2 => parse_v2(&mut reader)?,
+        version => return Err(SspmLoaderError::UnsupportedVersion(version)),
+    };
+    if map.notes.0.is_empty() {
+        return Err(SspmLoaderError::NoNotes);
+    }
+    return Ok(map);
+}
+
+fn parse_v1(reader: &mut SspmReader) -> Result<SspmMap, SspmLoaderError> {
+    reader.skip(2)?;// Reserved
+    let id = reader.line()?;
+    let name = reader.line()?;
+    let creator = reader.line()?;
+    let _last_note_ms = reader.u32()?;
+    let note_count = reader.u32()?;
+    let difficulty = reader.u8()?;
+
+    let cover = match reader.u8()? {
+        // Legacy Godot image, it can't be decoded outside of the original client so it's skipped
+        1 => {
+            reader.skip(6)?;// Height, width, mipmaps and format
+            let length = reader.u64()? as usize;
+            reader.skip(length)?;
+            None
+        }
+        2 => {
+            let length = reader.u64()? as usize;
+            Some(reader.bytes(length)?.to_vec())
+        }
+        _ => None,
+    };
+    let audio = match reader.u8()? {
+        1 => {
+            let length = reader.u64()? as usize;
+            Some(reader.bytes(length)?.to_vec())
+        }
+        _ => None,
+    };
+
+    let mut notes = vec![];
+    for _ in 0..note_count {
+        let hit_ms = reader.u32()? as i128;
+        let (x, y) = reader.position()?;
+        notes.push(grid_note(hit_ms, x, y));
+    }
+    notes.sort_by_key(|note| note.hit_ms);
+
+    Ok(SspmMap {
+        id,
+        name,
+        song_name: String::new(),
+        mappers: vec![creator],
+        difficulty,
+        difficulty_name: None,
+        notes: NoteData(notes),
+        audio,
+        cover,
+    })
+}
+
+fn parse_v2(reader: &mut SspmReader) -> Result<SspmMap, SspmLoaderError> {
+    reader.skip(4)?;// Reserved
+    reader.skip(20)?;// SHA-1 of the marker block
+    let _last_marker_ms = reader.u32()?;
+    let _note_count = reader.u32()?;
+    let marker_count = reader.u32()?;
+    let difficulty = reader.u8()?;
+    let _star_rating = reader.u16()?;
+    let has_audio = reader.u8()? != 0;
+    let has_cover = reader.u8()? != 0;
+    let _requires_mod = reader.u8()? != 0;
+    let custom_data = reader.block()?;
+    let audio_block = reader.block()?;
+    let cover_block = reader.block()?;
+    let marker_definitions = reader.block()?;
+    let markers = reader.block()?;
+
+    let id = reader.string()?;
+    let name = reader.string()?;
+    let song_name = reader.string()?;
+    let mapper_count = reader.u16()?;
+    let mut mappers = vec![];
+    for _ in 0..mapper_count {
+        mappers.push(reader.string()?);
+    }
+
+    // Custom data is optional, the only field we care about is the difficulty name
+    let mut difficulty_name = None;
+    if custom_data.1 > 0 {
+        reader.seek(custom_data.0)?;
+        let field_count = reader.u16()?;
+        for _ in 0..field_count {
+            let field = reader.string()?;
+            let value = reader.value()?;
+            if field == DIFFICULTY_NAME_FIELD {
+                if let SspmValue::String(name) = value {
+                    difficulty_name = Some(name);
+                }
+            }
+        }
+    }
+
+    let audio = if has_audio {
+        Some(reader.slice(audio_block)?.to_vec())
+    } else {
+        None
+    };
+    let cover = if has_cover {
+        Some(reader.slice(cover_block)?.to_vec())
+    } else {
+        None
+    };
+
+    // Marker definitions, each one is a name followed by the types of its values
+    reader.seek(marker_definitions.0)?;
+    let definition_count = reader.u8()?;
+    let mut definitions: Vec<(String, Vec<u8>)> = vec![];
+    for _ in 0..definition_count {
+        let name = reader.string()?;
+        let value_count = reader.u8()?;
+        let types = reader.bytes(value_count as usize)?.to_vec();
+        reader.skip(1)?;// End of definition
+        definitions.push((name, types));
+    }
+
+    reader.seek(markers.0)?;
+    let mut notes = vec![];
+    for _ in 0..marker_count {
+        let hit_ms = reader.u32()? as i128;
+        let definition_offset = reader.offset();
+        let definition = reader.u8()?;
+        let Some((name, types)) = definitions.get(definition as usize) else {
+            return Err(SspmLoaderError::UnknownMarker { marker: definition, offset: definition_offset });
+        };
+        let mut values = vec![];
+        for value_type in types {
+            values.push(reader.typed_value(*value_type)?);
+        }
+        if name != NOTE_MARKER {
+            continue;
+        }
+        if let Some(SspmValue::Position(x, y)) = values.first() {
+            notes.push(grid_note(hit_ms, *x, *y));
+        }
+    }
+    notes.sort_by_key(|note| note.hit_ms);
+
+    Ok(SspmMap {
+        id,
+        name,
+        song_name,
+        mappers,
+        difficulty,
+        difficulty_name,
+        notes: NoteData(notes),
+        audio,
+        cover,
+    })
+}
+
+// Writes the map as a v2 SSPM, notes are the only markers.
+// Positions on the grid are written as bytes, other ones (quantum) as floats.
+pub fn write_sspm(map: &SspmMap) -> Result<Vec<u8>, SspmWriterError> {
+    let mut strings = SspmWriter::default();
+    strings.string(&map.id, "id")?;
+    strings.string(&map.name, "name")?;
+    strings.string(&map.song_name, "song name")?;
+    let mapper_count = u16::try_from(map.mappers.len()).map_err(|_| SspmWriterError::StringTooLong { field: "mapper list" })?;
+    strings.u16(mapper_count);
+    for mapper in &map.mappers {
+        strings.string(mapper, "mapper")?;
+    }
+
+    let mut custom_data = SspmWriter::default();
+    custom_data.u16(map.difficulty_name.is_some() as u16);
+    if let Some(difficulty_name) = &map.difficulty_name {
+        custom_data.string(DIFFICULTY_NAME_FIELD, "difficulty name")?;
+        custom_data.u8(0x09);
+        custom_data.string(difficulty_name, "difficulty name")?;
+    }
+
+    let mut marker_definitions = SspmWriter::default();
+    marker_definitions.u8(1);
+    marker_definitions.string(NOTE_MARKER, "marker name")?;
+    marker_definitions.u8(1);// One value, the position
+    marker_definitions.u8(0x07);
+    marker_definitions.u8(0);// End of definition
+
+    let mut markers = SspmWriter::default();
+    for (i, note) in map.notes.0.iter().enumerate() {
+        if note.size != 1.0 {
+            return Err(SspmWriterError::UnsupportedSize { note: i, size: note.size });
+        }
+        let hit_ms = u32::try_from(note.hit_ms).map_err(|_| SspmWriterError::InvalidHitTime { note: i, hit_ms: note.hit_ms })?;
+        markers.u32(hit_ms);
+        markers.u8(0);// Note definition
+        // + 1.0 to convert from -1 - 1 to 0-2
+        let (x, y) = (note.x + 1., note.y + 1.);
+        let on_grid = |value: f32| value.fract() == 0. && (0. ..=2.).contains(&value);
+        if on_grid(x) && on_grid(y) {
+            markers.u8(0);
+            markers.u8(x as u8);
+            markers.u8(y as u8);
+        } else {
+            markers.u8(1);
+            markers.bytes(&x.to_le_bytes());
+            markers.bytes(&y.to_le_bytes());
+        }
+    }
+
+    let audio = map.audio.as_deref().unwrap_or_default();
+    let cover = map.cover.as_deref().unwrap_or_default();
+    // Fixed size header, then the strings and the blocks in the order of their offsets
+    const HEADER_LENGTH: usize = 128;
+    let mut offset = HEADER_LENGTH + strings.bytes.len();
+    let mut block = |length: usize| {
+        let block = (offset as u64, length as u64);
+        offset += length;
+        block
+    };
+    let blocks = [
+        block(custom_data.bytes.len()),
+        block(audio.len()),
+        block(cover.len()),
+        block(marker_definitions.bytes.len()),
+        block(markers.bytes.len()),
+    ];
+
+    let mut file = SspmWriter::default();
+    file.bytes(SIGNATURE);
+    file.u16(2);
+    file.bytes(&[0; 4]);// Reserved
+    // SHA-1 of the marker block, for clients that check it
+    file.bytes(&Sha1::digest(&markers.bytes));
+    file.u32(map.notes.length_ms() as u32);
+    file.u32(map.notes.0.len() as u32);
+    file.u32(map.notes.0.len() as u32);
+    file.u8(map.difficulty);
+    file.u16(0);// Star rating
+    file.u8(map.audio.is_some() as u8);
+    file.u8(map.cover.is_some() as u8);
+    file.u8(0);// Requires a mod
+    for (block_offset, length) in blocks {
+        file.u64(block_offset);
+        file.u64(length);
+    }
+    file.bytes(&strings.bytes);
+    file.bytes(&custom_data.bytes);
+    file.bytes(audio);
+    file.bytes(cover);
+    file.bytes(&marker_definitions.bytes);
+    file.bytes(&markers.bytes);
+    return Ok(file.bytes);
+}
+
+// SSPM positions go from 0 to 2 like the legacy format, - 1.0 to convert to -1 - 1
+fn grid_note(hit_ms: i128, x: f32, y: f32) -> Note {
+    Note {
+        hit_ms,
+        x: x - 1.,
+        y: y - 1.,
+        size: 1.0,
+    }
+}
+
+// Only the values the game uses are kept, everything else is read and discarded
+enum SspmValue {
+    Position(f32, f32),
+    String(String),
+    Other
+}
+
+struct SspmReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> SspmReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: 0
+        }
+    }
+
+    fn offset(&self) -> usize {
+        return self.offset;
+    }
+
+    fn seek(&mut self, offset: usize) -> Result<(), SspmLoaderError> {
+        if offset > self.bytes.len() {
+            return Err(SspmLoaderError::UnexpectedEof { offset });
+        }
+        self.offset = offset;
+        return Ok(());
+    }
+
+    fn skip(&mut self, length: usize) -> Result<(), SspmLoaderError> {
+        self.bytes(length)?;
+        return Ok(());
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], SspmLoaderError> {
+        let end = self.offset.checked_add(length).filter(|end| *end <= self.bytes.len());
+        let Some(end) = end else {
+            return Err(SspmLoaderError::UnexpectedEof { offset: self.bytes.len() });
+        };
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        return Ok(bytes);
+    }
+
+    fn slice(&self, (offset, length): (usize, usize)) -> Result<&'a [u8], SspmLoaderError> {
+        let end = offset.checked_add(length).filter(|end| *end <= self.bytes.len());
+        let Some(end) = end else {
+            return Err(SspmLoaderError::UnexpectedEof { offset: self.bytes.len() });
+        };
+        return Ok(&self.bytes[offset..end]);
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SspmLoaderError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        return Ok(array);
+    }
+
+    fn u8(&mut self) -> Result<u8, SspmLoaderError> {
+        return Ok(self.array::<1>()?[0]);
+    }
+
+    fn u16(&mut self) -> Result<u16, SspmLoaderError> {
+        return Ok(u16::from_le_bytes(self.array()?));
+    }
+
+    fn u32(&mut self) -> Result<u32, SspmLoaderError> {
+        return Ok(u32::from_le_bytes(self.array()?));
+    }
+
+    fn u64(&mut self) -> Result<u64, SspmLoaderError> {
+        return Ok(u64::from_le_bytes(self.array()?));
+    }
+
+    fn f32(&mut self) -> Result<f32, SspmLoaderError> {
+        return Ok(f32::from_le_bytes(self.array()?));
+    }
+
+    // An offset and length pair pointing to a block of the file
+    fn block(&mut self) -> Result<(usize, usize), SspmLoaderError> {
+        return Ok((self.u64()? as usize, self.u64()? as usize));
+    }
+
+    fn utf8(&mut self, length: usize) -> Result<String, SspmLoaderError> {
+        let offset = self.offset;
+        let bytes = self.bytes(length)?;
+        return String::from_utf8(bytes.to_vec()).map_err(|err| SspmLoaderError::InvalidUtf8 { offset: offset + err.utf8_error().valid_up_to() });
+    }
+
+    // v1 strings are terminated by a newline
+    fn line(&mut self) -> Result<String, SspmLoaderError> {
+        let Some(length) = self.bytes[self.offset..].iter().position(|byte| *byte == b'\n') else {
+            return Err(SspmLoaderError::UnexpectedEof { offset: self.bytes.len() });
+        };
+        let line = self.utf8(length)?;
+        self.skip(1)?;
+        return Ok(line);
+    }
+
+    // v2 strings are prefixed by their length
+    fn string(&mut self) -> Result<String, SspmLoaderError> {
+        let length = self.u16()? as usize;
+        return self.utf8(length);
+    }
+
+    fn position(&mut self) -> Result<(f32, f32), SspmLoaderError> {
+        let is_quantum = self.u8()? != 0;
+        if is_quantum {
+            return Ok((self.f32()?, self.f32()?));
+        }
+        return Ok((self.u8()? as f32, self.u8()? as f32));
+    }
+
+    fn value(&mut self) -> Result<SspmValue, SspmLoaderError> {
+        let value_type = self.u8()?;
+        return self.typed_value(value_type);
+    }
+
+    fn typed_value(&mut self, value_type: u8) -> Result<SspmValue, SspmLoaderError> {
+        let offset = self.offset;
+        return Ok(match value_type {
+            0x01 => {
+                self.skip(1)?;
+                SspmValue::Other
+            }
+            0x02 => {
+                self.skip(2)?;
+                SspmValue::Other
+            }
+            0x03 | 0x05 => {
+                self.skip(4)?;
+                SspmValue::Other
+            }
+            0x04 | 0x06 => {
+                self.skip(8)?;
+                SspmValue::Other
+            }
+            0x07 => {
+                let (x, y) = self.position()?;
+                SspmValue::Position(x, y)
+            }
+            0x08 => {
+                let length = self.u16()? as usize;
+                self.skip(length)?;
+                SspmValue::Other
+            }
+            0x09 => SspmValue::String(self.string()?),
+            0x0a => {
+                let length = self.u32()? as usize;
+                self.skip(length)?;
+                SspmValue::Other
+            }
+            0x0b => {
+                let length = self.u32()? as usize;
+                SspmValue::String(self.utf8(length)?)
+            }
+            0x0c => {
+                let item_type = self.u8()?;
+                let length = self.u16()?;
+                for _ in 0..length {
+                    self.typed_value(item_type)?;
+                }
+                SspmValue::Other
+            }
+            _ => return Err(SspmLoaderError::UnknownValueType { value_type, offset }),
+        });
+    }
+}
+
+#[derive(Default)]
+struct SspmWriter {
+    bytes: Vec<u8>,
+}
+
+impl SspmWriter {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str, field: &'static str) -> Result<(), SspmWriterError> {
+        let length = u16::try_from(value.len()).map_err(|_| SspmWriterError::StringTooLong { field })?;
+        self.u16(length);
+        self.bytes(value.as_bytes());
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha1::{Digest, Sha1};
+
+    use super::{parse_sspm, write_sspm, Note, NoteData, SspmLoaderError, SspmMap, SspmWriter, SIGNATURE};
+
+    fn parse_err(bytes: &[u8]) -> SspmLoaderError {
+        match parse_sspm(bytes) {
+            Ok(map) => panic!("parsed into {0} notes", map.notes.0.len()),
+            Err(err) => return err,
+        }
+    }
+
+    fn notes(map: &SspmMap) -> Vec<(i128, f32, f32)> {
+        return map.notes.0.iter().map(|note| (note.hit_ms, note.x, note.y)).collect();
+    }
+
+    // v1: newline terminated strings, a Godot or PNG cover, the audio, then every note
+    fn v1_blob(cover_type: u8) -> Vec<u8> {
+        let mut file = SspmWriter::default();
+        file.bytes(SIGNATURE);
+        file.u16(1);
+        file.bytes(&[0; 2]);
+        file.bytes(b"map_id\nArtist - Song\nSomeone\n");
+        file.u32(100);
+        file.u32(2);
+        file.u8(3);
+        file.u8(cover_type);
+        match cover_type {
+            1 => {
+                file.bytes(&[0; 6]);
+                file.u64(4);
+                file.bytes(&[9; 4]);
+            }
+            2 => {
+                file.u64(2);
+                file.bytes(&[7, 8]);
+            }
+            _ => {}
+        }
+        file.u8(1);
+        file.u64(3);
+        file.bytes(&[1, 2, 3]);
+        // On the grid, then quantum
+        file.u32(100);
+        file.u8(0);
+        file.u8(2);
+        file.u8(0);
+        file.u32(50);
+        file.u8(1);
+        file.bytes(&1.5_f32.to_le_bytes());
+        file.bytes(&0.5_f32.to_le_bytes());
+        return file.bytes;
+    }
+
+    // v2: a fixed header pointing to the custom data, audio, cover, marker definitions and markers blocks
+    fn v2_blob(custom_data: &[u8], marker_definitions: &[u8], markers: &[u8], marker_count: u32) -> Vec<u8> {
+        let mut strings = SspmWriter::default();
+        for value in ["map_id", "Map Name", "Artist - Song"] {
+            strings.string(value, "test").unwrap();
+        }
+        strings.u16(2);
+        strings.string("First", "test").unwrap();
+        strings.string("Second", "test").unwrap();
+
+        let mut offset = 128 + strings.bytes.len();
+        let mut file = SspmWriter::default();
+        file.bytes(SIGNATURE);
+        file.u16(2);
+        file.bytes(&[0; 4]);
+        file.bytes(&Sha1::digest(markers));
+        file.u32(0);
+        file.u32(0);
+        file.u32(marker_count);
+        file.u8(4);
+        file.u16(0);
+        file.u8(0);
+        file.u8(0);
+        file.u8(0);
+        for block in [custom_data, &[], &[], marker_definitions, markers] {
+            file.u64(offset as u64);
+            file.u64(block.len() as u64);
+            offset += block.len();
+        }
+        assert_eq!(file.bytes.len(), 128);
+        file.bytes(&strings.bytes);
+        for block in [custom_data, marker_definitions, markers] {
+            file.bytes(block);
+        }
+        return file.bytes;
+    }
+
+    // A note marker and a marker the game doesn't use, with a bool value
+    fn v2_definitions() -> Vec<u8> {
+        let mut definitions = SspmWriter::default();
+        definitions.u8(2);
+        definitions.string("ssp_note", "test").unwrap();
+        definitions.bytes(&[1, 0x07, 0]);
+        definitions.string("flash", "test").unwrap();
+        definitions.bytes(&[1, 0x01, 0]);
+        return definitions.bytes;
+    }
+
+    fn v2_markers() -> Vec<u8> {
+        let mut markers = SspmWriter::default();
+        markers.u32(300);
+        markers.bytes(&[0, 0, 2, 2]);
+        markers.u32(200);
+        markers.bytes(&[1, 1]);
+        markers.u32(100);
+        markers.bytes(&[0, 1]);
+        markers.bytes(&0.25_f32.to_le_bytes());
+        markers.bytes(&2_f32.to_le_bytes());
+        return markers.bytes;
+    }
+
+    fn v2_custom_data() -> Vec<u8> {
+        let mut custom_data = SspmWriter::default();
+        custom_data.u16(1);
+        custom_data.string("difficulty_name", "test").unwrap();
+        custom_data.u8(0x09);
+        custom_data.string("Extreme", "test").unwrap();
+        return custom_data.bytes;
+    }
+
+    #[test]
+    fn parses_v1() {
+        let map = parse_sspm(&v1_blob(0)).unwrap_or_else(|err| panic!("{0}", err));
+        assert_eq!((map.id.as_str(), map.name.as_str(), map.mappers.clone()), ("map_id", "Artist - Song", vec!["Someone".to_owned()]));
+        assert_eq!(map.difficulty_name(), "Hard");
+        assert_eq!(notes(&map), vec![(50, 0.5, -0.5), (100, 1., -1.)]);
+        assert_eq!(map.audio.as_deref(), Some([1, 2, 3].as_slice()));
+        assert_eq!(map.cover, None);
+
+        let metadata = map.metadata();
+        assert_eq!((metadata.artist.as_str(), metadata.title.as_str(), metadata.mapper.as_str()), ("Artist", "Song", "Someone"));
+    }
+
+    #[test]
+    fn v1_godot_covers_are_skipped() {
+        let map = parse_sspm(&v1_blob(1)).unwrap_or_else(|err| panic!("{0}", err));
+        assert_eq!(map.cover, None);
+        assert_eq!(notes(&map).len(), 2);
+
+        let map = parse_sspm(&v1_blob(2)).unwrap_or_else(|err| panic!("{0}", err));
+        assert_eq!(map.cover.as_deref(), Some([7, 8].as_slice()));
+        assert_eq!(map.audio.as_deref(), Some([1, 2, 3].as_slice()));
+    }
+
+    #[test]
+    fn parses_v2() {
+        let map = parse_sspm(&v2_blob(&v2_custom_data(), &v2_definitions(), &v2_markers(), 3)).unwrap_or_else(|err| panic!("{0}", err));
+        assert_eq!((map.id.as_str(), map.name.as_str(), map.song_name.as_str()), ("map_id", "Map Name", "Artist - Song"));
+        assert_eq!(map.mappers, vec!["First".to_owned(), "Second".to_owned()]);
+        assert_eq!(map.difficulty_name(), "Extreme");
+        // The flash marker is not a note
+        assert_eq!(notes(&map), vec![(100, -0.75, 1.), (300, 1., 1.)]);
+        assert_eq!((map.audio.clone(), map.cover.clone()), (None, None));
+        assert_eq!(map.metadata().mapper, "First, Second");
+    }
+
+    #[test]
+    fn v2_custom_data_is_optional() {
+        let map = parse_sspm(&v2_blob(&[], &v2_definitions(), &v2_markers(), 3)).unwrap_or_else(|err| panic!("{0}", err));
+        assert_eq!(map.difficulty_name(), "Logic");
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(matches!(parse_err(b""), SspmLoaderError::UnexpectedEof { offset: 0 }));
+        assert!(matches!(parse_err(b"RIFF\x01\x00"), SspmLoaderError::InvalidSignature));
+        assert!(matches!(parse_err(b"SS+m\x03\x00"), SspmLoaderError::UnsupportedVersion(3)));
+        assert!(matches!(parse_err(&v2_blob(&[], &v2_definitions(), &[], 0)), SspmLoaderError::NoNotes));
+        // The marker uses a definition that doesn't exist
+        let mut markers = v2_markers();
+        markers[4] = 5;
+        assert!(matches!(parse_err(&v2_blob(&[], &v2_definitions(), &markers, 3)), SspmLoaderError::UnknownMarker { marker: 5, .. }));
+        let mut custom_data = v2_custom_data();
+        let value_type = custom_data.len() - "Extreme".len() - 3;
+        custom_data[value_type] = 0x42;
+        assert!(matches!(parse_err(&v2_blob(&custom_data, &v2_definitions(), &v2_markers(), 3)), SspmLoaderError::UnknownValueType { value_type: 0x42, .. }));
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        for blob in [v1_blob(1), v1_blob(2), v2_blob(&v2_custom_data(), &v2_definitions(), &v2_markers(), 3)] {
+            for end in 0..blob.len() {
+                assert!(parse_sspm(&blob[..end]).is_err(), "{0} of {1} bytes", end, blob.len());
+            }
+        }
+    }
+
+    #[test]
+    fn written_maps_parse_back() {
+        let map = SspmMap {
+            id: "written".to_owned(),
+            name: "Artist - Song".to_owned(),
+            song_name: "Artist - Song".to_owned(),
+            mappers: vec!["Someone".to_owned()],
+            difficulty: 2,
+            difficulty_name: Some("Custom".to_owned()),
+            notes: NoteData(vec![
+                Note { hit_ms: 0, x: -1., y: 1., size: 1. },
+                Note { hit_ms: 250, x: 0.375, y: -1.25, size: 1. },
+            ]),
+            audio: Some(vec![1, 2, 3, 4]),
+            cover: Some(vec![5, 6]),
+        };
+        let bytes = write_sspm(&map).unwrap_or_else(|err| panic!("{0}", err));
+        let parsed = parse_sspm(&bytes).unwrap_or_else(|err| panic!("{0}", err));
+        assert_eq!(parsed.notes.0, map.notes.0);
+        assert_eq!((parsed.id, parsed.name, parsed.song_name, parsed.mappers), (map.id, map.name, map.song_name, map.mappers));
+        assert_eq!((parsed.difficulty, parsed.difficulty_name), (2, Some("Custom".to_owned())));
+        assert_eq!((parsed.audio, parsed.cover), (map.audio, map.cover));
+
+        // The header has the SHA-1 of the marker block, which ends the file
+        let markers_start = u64::from_le_bytes(bytes[112..120].try_into().unwrap()) as usize;
+        assert_eq!(bytes[10..30], Sha1::digest(&bytes[markers_start..])[..]);
+    }
+}
@@ -0,0 +1,282 @@
+use std::{fmt::{Display, Formatter}, str::{from_utf8, FromStr}};
+
+use bevy::{asset::{AssetLoader, AsyncReadExt}, utils::BoxedFuture};
+use thiserror::Error;
+
+use super::{Note, NoteData};
+
+#[derive(Default)]
+pub struct V1NoteDataLoader;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoteField {
+    X,
+    Y,
+    HitTime,
+    Size
+}
+
+impl Display for NoteField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            NoteField::X => "x",
+            NoteField::Y => "y",
+            NoteField::HitTime => "hit time",
+            NoteField::Size => "size",
+        })
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum V1NoteDataLoaderError {
+    #[error("Could not read map: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Map is not valid UTF-8 (byte {offset})")]
+    InvalidUtf8 { offset: usize },
+    #[error("Map has no notes")]
+    NoNotes,
+    #[error("Note {note} is missing its {field} field (byte {offset})")]
+    MissingField { note: usize, field: NoteField, offset: usize },
+    #[error("Note {note} has an invalid {field} value {value:?} (byte {offset})")]
+    InvalidNumber { note: usize, field: NoteField, offset: usize, value: String },
+    #[error("Note {note} has too many fields (byte {offset})")]
+    TooManyFields { note: usize, offset: usize }
+}
+
+impl AssetLoader for V1NoteDataLoader {
+    type Asset = NoteData;
+
+    type Settings = ();
+
+    type Error = V1NoteDataLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut bevy::asset::LoadContext,
+    ) -> BoxedFuture<'a, Result<NoteData, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            parse_v1_notes(&bytes)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["txt"]
+    }
+}
+
+// Parses the legacy Roblox format: "<map id>,x|y|ms,x|y|ms,..." with x and y from 0 to 2.
+// Positions don't have to be on the grid (quantum), and a note can have an optional size: "x|y|ms|size".
+// Whitespace, newlines and empty entries (e.g. a trailing comma) are ignored.
+pub fn parse_v1_notes(bytes: &[u8]) -> Result<NoteData, V1NoteDataLoaderError> {
+    let text = from_utf8(bytes).map_err(|err| V1NoteDataLoaderError::InvalidUtf8 { offset: err.valid_up_to() })?;
+    let mut notes: Vec<Note> = vec![];
+    let mut offset = 0;
+    // Skip the first entry since it's just the name/roblox id
+    for (i, entry) in text.split(',').enumerate() {
+        let entry_offset = offset;
+        offset += entry.len() + 1;
+        if i == 0 || entry.trim().is_empty() {
+            continue;
+        }
+
+        let note = notes.len();
+        let has_size = entry.matches('|').count() >= 3;
+        let mut fields = entry.split('|');
+        let mut field_offset = entry_offset;
+        let mut next_field = |field: NoteField| {
+            let Some(value) = fields.next() else {
+                return Err(V1NoteDataLoaderError::MissingField { note, field, offset: entry_offset + entry.len() });
+            };
+            let value_offset = field_offset + (value.len() - value.trim_start().len());
+            field_offset += value.len() + 1;
+            let value = value.trim();
+            if value.is_empty() {
+                return Err(V1NoteDataLoaderError::MissingField { note, field, offset: value_offset });
+            }
+            return Ok((value, value_offset));
+        };
+        // - 1.0 to convert from 0-2 to -1 - 1
+        let x = parse_coordinate(note, NoteField::X, next_field(NoteField::X)?)? - 1.;
+        let y = parse_coordinate(note, NoteField::Y, next_field(NoteField::Y)?)? - 1.;
+        let hit_ms = parse_field::<i128>(note, NoteField::HitTime, next_field(NoteField::HitTime)?)?;
+        let size = if has_size {
+            let (value, value_offset) = next_field(NoteField::Size)?;
+            match parse_field::<f32>(note, NoteField::Size, (value, value_offset))? {
+                size if size > 0. && size.is_finite() => size,
+                _ => return Err(V1NoteDataLoaderError::InvalidNumber { note, field: NoteField::Size, offset: value_offset, value: value.to_owned() }),
+            }
+        } else {
+            1.0
+        };
+        if fields.next().is_some() {
+            return Err(V1NoteDataLoaderError::TooManyFields { note, offset: field_offset });
+        }
+        notes.push(Note {
+            hit_ms,
+            x,
+            y,
+            size,
+        });
+    }
+
+    if notes.is_empty() {
+        return Err(V1NoteDataLoaderError::NoNotes);
+    }
+    notes.sort_by_key(|note| note.hit_ms);
+    Ok(NoteData(notes))
+}
+
+fn parse_field<T: FromStr>(note: usize, field: NoteField, (value, offset): (&str, usize)) -> Result<T, V1NoteDataLoaderError> {
+    value.parse().map_err(|_| V1NoteDataLoaderError::InvalidNumber { note, field, offset, value: value.to_owned() })
+}
+
+// nan and inf parse as floats but can't be placed on the grid
+fn parse_coordinate(note: usize, field: NoteField, (value, offset): (&str, usize)) -> Result<f32, V1NoteDataLoaderError> {
+    match parse_field::<f32>(note, field, (value, offset))? {
+        coordinate if coordinate.is_finite() => Ok(coordinate),
+        _ => Err(V1NoteDataLoaderError::InvalidNumber { note, field, offset, value: value.to_owned() }),
+    }
+}
+
+// Writes the notes back in the legacy format, the size is only written when it isn't the default one.
+// The id can't have commas since they separate the entries.
+pub fn write_v1_notes(id: &str, notes: &NoteData) -> String {
+    let mut text = id.replace(',', "");
+    for note in &notes.0 {
+        // + 1.0 to convert from -1 - 1 to 0-2
+        text.push_str(&format!(",{0}|{1}|{2}", note.x + 1., note.y + 1., note.hit_ms));
+        if note.size != 1.0 {
+            text.push_str(&format!("|{0}", note.size));
+        }
+    }
+    return text;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_v1_notes, write_v1_notes, NoteData, NoteField, V1NoteDataLoaderError};
+
+    fn parse(text: &str) -> Result<NoteData, V1NoteDataLoaderError> {
+        return parse_v1_notes(text.as_bytes());
+    }
+
+    fn parse_err(text: &str) -> V1NoteDataLoaderError {
+        match parse(text) {
+            Ok(notes) => panic!("{0:?} parsed into {1} notes", text, notes.0.len()),
+            Err(err) => return err,
+        }
+    }
+
+    #[test]
+    fn parses_notes_in_hit_order() {
+        let notes = parse("1234,2|0|500,1|1|100,0.5|1.25|300|1.5").ok().unwrap();
+        let parsed: Vec<(i128, f32, f32, f32)> = notes.0.iter().map(|note| (note.hit_ms, note.x, note.y, note.size)).collect();
+        assert_eq!(parsed, vec![(100, 0., 0., 1.), (300, -0.5, 0.25, 1.5), (500, 1., -1., 1.)]);
+    }
+
+    #[test]
+    fn empty_file_has_no_notes() {
+        assert!(matches!(parse_err(""), V1NoteDataLoaderError::NoNotes));
+        assert!(matches!(parse_err("  \n"), V1NoteDataLoaderError::NoNotes));
+        // Only the map id
+        assert!(matches!(parse_err("1234"), V1NoteDataLoaderError::NoNotes));
+        assert!(matches!(parse_err("1234,,"), V1NoteDataLoaderError::NoNotes));
+    }
+
+    #[test]
+    fn empty_entries_and_whitespace_are_skipped() {
+        for text in ["1234,1|1|100,", "1234,1|1|100,,", "1234,,1|1|100", "1234, 1 | 1 | 100 ,\n", "1234,1|1|100,\r\n"] {
+            let notes = parse(text).unwrap_or_else(|err| panic!("{0:?}: {1}", text, err));
+            assert_eq!(notes.0.len(), 1, "{0:?}", text);
+            assert_eq!(notes.0[0].hit_ms, 100, "{0:?}", text);
+        }
+        let notes = parse("1234,1|1|100,,,2|2|200,").ok().unwrap();
+        assert_eq!(notes.0.len(), 2);
+    }
+
+    #[test]
+    fn truncated_entries_are_missing_fields() {
+        assert!(matches!(parse_err("1234,1|1|100,1|1"), V1NoteDataLoaderError::MissingField { note: 1, field: NoteField::HitTime, offset: 16 }));
+        assert!(matches!(parse_err("1234,1|1|100,1|"), V1NoteDataLoaderError::MissingField { note: 1, field: NoteField::Y, offset: 15 }));
+        assert!(matches!(parse_err("1234,1|1|"), V1NoteDataLoaderError::MissingField { note: 0, field: NoteField::HitTime, offset: 9 }));
+        // The trailing `|` says there is a size
+        assert!(matches!(parse_err("1234,1|1|100|"), V1NoteDataLoaderError::MissingField { note: 0, field: NoteField::Size, offset: 13 }));
+    }
+
+    #[test]
+    fn missing_separators_are_missing_fields() {
+        assert!(matches!(parse_err("1234,1"), V1NoteDataLoaderError::MissingField { note: 0, field: NoteField::Y, offset: 6 }));
+        assert!(matches!(parse_err("1234,1|1"), V1NoteDataLoaderError::MissingField { note: 0, field: NoteField::HitTime, offset: 8 }));
+        assert!(matches!(parse_err("1234,||100"), V1NoteDataLoaderError::MissingField { note: 0, field: NoteField::X, offset: 5 }));
+        assert!(matches!(parse_err("1234,1||100|1"), V1NoteDataLoaderError::MissingField { note: 0, field: NoteField::Y, offset: 7 }));
+        // Spaces are not separators
+        match parse_err("1234,1 1 100") {
+            V1NoteDataLoaderError::InvalidNumber { note: 0, field: NoteField::X, offset: 5, value } => assert_eq!(value, "1 1 100"),
+            err => panic!("{0}", err),
+        }
+    }
+
+    #[test]
+    fn non_numeric_values_are_invalid() {
+        let cases = [
+            ("1234,a|1|100", 0, NoteField::X, 5, "a"),
+            ("1234,1|y|100", 0, NoteField::Y, 7, "y"),
+            ("1234,nan|inf|100", 0, NoteField::X, 5, "nan"),
+            ("1234,1|inf|100", 0, NoteField::Y, 7, "inf"),
+            ("1234,-inf|1|100", 0, NoteField::X, 5, "-inf"),
+            ("1234,1|1|100,1|NaN|200", 1, NoteField::Y, 15, "NaN"),
+            ("1234,1|1|100,1|1|soon", 1, NoteField::HitTime, 17, "soon"),
+            // Hit times are whole milliseconds
+            ("1234,1|1|100.5", 0, NoteField::HitTime, 9, "100.5"),
+            ("1234,1|1|100|big", 0, NoteField::Size, 13, "big"),
+            ("1234,1|1|100|0", 0, NoteField::Size, 13, "0"),
+            ("1234,1|1|100|-1", 0, NoteField::Size, 13, "-1"),
+            ("1234,1|1|100|inf", 0, NoteField::Size, 13, "inf"),
+        ];
+        for (text, expected_note, expected_field, expected_offset, expected_value) in cases {
+            match parse_err(text) {
+                V1NoteDataLoaderError::InvalidNumber { note, field, offset, value } => {
+                    assert_eq!((note, field, offset, value.as_str()), (expected_note, expected_field, expected_offset, expected_value), "{0:?}", text);
+                }
+                err => panic!("{0:?}: {1}", text, err),
+            }
+        }
+    }
+
+    #[test]
+    fn extra_fields_are_rejected() {
+        assert!(matches!(parse_err("1234,1|1|100|1|2"), V1NoteDataLoaderError::TooManyFields { note: 0, offset: 15 }));
+        assert!(matches!(parse_err("1234,1|1|100,1|1|200|1|"), V1NoteDataLoaderError::TooManyFields { note: 1, offset: 23 }));
+    }
+
+    #[test]
+    fn invalid_utf8_is_rejected() {
+        assert!(matches!(parse_v1_notes(b"1234,1|1|\xff00"), Err(V1NoteDataLoaderError::InvalidUtf8 { offset: 9 })));
+    }
+
+    #[test]
+    fn truncating_a_map_anywhere_does_not_panic() {
+        let text = "1234, 0|2|100,1.5|0.5|250|1.25,2|2|400,\n";
+        for end in 0..=text.len() {
+            // Every prefix either parses or is one of the errors, never a panic
+            match parse(&text[..end]) {
+                Ok(notes) => assert!(!notes.0.is_empty()),
+                Err(V1NoteDataLoaderError::NoNotes | V1NoteDataLoaderError::MissingField { .. } | V1NoteDataLoaderError::InvalidNumber { .. }) => {}
+                Err(err) => panic!("{0:?}: {1}", &text[..end], err),
+            }
+        }
+    }
+
+    #[test]
+    fn written_notes_parse_back() {
+        let notes = parse("1234,2|0|500,1|1|100,0.5|1.25|300|1.5").ok().unwrap();
+        let written = write_v1_notes("12,34", &notes);
+        assert!(written.starts_with("1234,"));
+        assert_eq!(parse(&written).ok().unwrap().0, notes.0);
+    }
+}
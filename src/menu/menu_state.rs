@@ -1,6 +1,8 @@
-use bevy::{app::{App, AppExit, Plugin, Update}, core_pipeline::core_2d::Camera2dBundle, ecs::{component::Component, entity::Entity, event::Events, query::{Changed, With, Without}, schedule::{common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit}, system::{Commands, EntityCommands, Query, Res, ResMut, Resource}}, hierarchy::{BuildChildren, ChildBuilder, DespawnRecursiveExt}, render::{camera::{Camera, ClearColorConfig}, color::Color}, text::{Text, TextSection, TextStyle}, ui::{node_bundles::{ButtonBundle, NodeBundle, TextBundle}, widget::Button, AlignItems, AlignSelf, BackgroundColor, FlexDirection, FlexWrap, Interaction, JustifyContent, JustifyItems, PositionType, Style, UiRect, Val}, utils::default};
+use bevy::{app::{App, AppExit, Plugin, Update}, asset::Assets, core_pipeline::core_2d::Camera2dBundle, ecs::{component::Component, entity::Entity, event::{EventReader, Events}, query::{Changed, With, Without}, schedule::{common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit}, system::{Commands, EntityCommands, Query, Res, ResMut, Resource}}, hierarchy::{BuildChildren, ChildBuilder, DespawnRecursiveExt}, log::{info, warn}, render::{camera::{Camera, ClearColorConfig}, color::Color}, text::{Text, TextSection, TextStyle}, ui::{node_bundles::{ButtonBundle, NodeBundle, TextBundle}, widget::Button, AlignItems, AlignSelf, BackgroundColor, FlexDirection, FlexWrap, Interaction, JustifyContent, JustifyItems, PositionType, Style, UiRect, Val}, utils::default, window::FileDragAndDrop};
 
-use crate::{play::play_state::{MapLoadPlayResource, PlayStateData}, startup::GlobalAssets, state::GameState};
+use crate::{input::{MenuAction, MenuInput}, library::MapLibrary, map::NoteData, modifiers::Modifiers, play::play_state::MapLoadPlayResource, replay::{Replay, REPLAY_EXTENSION}, scores::map_id, state::GameState};
+
+use super::{modifier_select::{self, build_modifier_select, ModifierFocus}, song_select::{self, build_song_select, SongPreview, SongSelection}};
 
 pub struct MenuStatePlugin;
 
@@ -8,21 +10,33 @@ pub struct MenuStatePlugin;
 pub struct OnMenu;
 
 #[derive(Component)]
-pub struct TestPlayButton;
+pub struct QuitGameButton;
 
 #[derive(Component)]
-pub struct QuitGameButton;
+pub struct OpenSettingsButton;
 
 impl Plugin for MenuStatePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(GameState::Menu), build_menu);
-        app.add_systems(OnExit(GameState::Menu), cleanup_menu);
-        app.add_systems(Update, on_test_play.run_if(in_state(GameState::Menu)));
-        app.add_systems(Update, on_quit_game.run_if(in_state(GameState::Menu)));
+        app.add_systems(OnExit(GameState::Menu), (cleanup_menu, song_select::stop_song_preview));
+        app.init_resource::<SongSelection>();
+        app.init_resource::<SongPreview>();
+        app.init_resource::<Modifiers>();
+        app.init_resource::<ModifierFocus>();
+        app.add_systems(Update, (
+            song_select::on_song_entry_click,
+            song_select::on_play_selected,
+            song_select::on_song_select_keys,
+            song_select::on_song_list_scroll,
+            song_select::update_song_select,
+            song_select::update_song_preview
+        ).chain().run_if(in_state(GameState::Menu)));
+        app.add_systems(Update, (modifier_select::on_modifier_click, modifier_select::on_modifier_keys, modifier_select::update_modifier_select).chain().run_if(in_state(GameState::Menu)));
+        app.add_systems(Update, (on_quit_game, on_open_settings, on_replay_dropped).run_if(in_state(GameState::Menu)));
     }
 }
 
-fn build_menu(globals: ResMut<GlobalAssets>, mut commands: Commands) {
+fn build_menu(library: Res<MapLibrary>, mut commands: Commands) {
     commands.spawn((NodeBundle {
         style: Style {
             width: Val::Percent(100.),
@@ -55,47 +69,57 @@ fn build_menu(globals: ResMut<GlobalAssets>, mut commands: Commands) {
                 ]),
             ..default()
         });
-        builder.spawn((ButtonBundle {
+        build_song_select(builder, &library);
+        build_modifier_select(builder);
+        builder.spawn(NodeBundle {
             style: Style {
-                width: Val::Px(400.),
-                height: Val::Px(70.),
-                justify_content: JustifyContent::Center,
-                align_items: AlignItems::Center,
-                margin: UiRect::px(10., 10., 10., 10.),
+                flex_direction: FlexDirection::Row,
                 ..default()
             },
-            background_color: BackgroundColor(Color::rgb(0.5, 0.05, 0.7)),
             ..default()
-        }, TestPlayButton)).with_children(|parent| {
-            parent.spawn(TextBundle::from_section(
-                "Play test map (BelowAmateur - birb)",
-                TextStyle {
-                    font_size: 30.0,
-                    color: Color::rgb(0.9, 0.9, 0.9),
+        }).with_children(|builder| {
+            builder.spawn((ButtonBundle {
+                style: Style {
+                    width: Val::Px(300.),
+                    height: Val::Px(70.),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    margin: UiRect::px(10., 10., 10., 10.),
                     ..default()
                 },
-            ));
-        });
-        builder.spawn((ButtonBundle {
-            style: Style {
-                width: Val::Px(400.),
-                height: Val::Px(70.),
-                justify_content: JustifyContent::Center,
-                align_items: AlignItems::Center,
-                margin: UiRect::px(10., 10., 10., 10.),
+                background_color: BackgroundColor(Color::rgb(0.2, 0.2, 0.2)),
                 ..default()
-            },
-            background_color: BackgroundColor(Color::rgb(0.7, 0.15, 0.0)),
-            ..default()
-        }, QuitGameButton)).with_children(|parent| {
-            parent.spawn(TextBundle::from_section(
-                "Quit game",
-                TextStyle {
-                    font_size: 30.0,
-                    color: Color::rgb(0.9, 0.9, 0.9),
+            }, OpenSettingsButton)).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "Settings",
+                    TextStyle {
+                        font_size: 30.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..default()
+                    },
+                ));
+            });
+            builder.spawn((ButtonBundle {
+                style: Style {
+                    width: Val::Px(300.),
+                    height: Val::Px(70.),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    margin: UiRect::px(10., 10., 10., 10.),
                     ..default()
                 },
-            ));
+                background_color: BackgroundColor(Color::rgb(0.7, 0.15, 0.0)),
+                ..default()
+            }, QuitGameButton)).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "Quit game",
+                    TextStyle {
+                        font_size: 30.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..default()
+                    },
+                ));
+            });
         });
     });
 
@@ -108,12 +132,11 @@ fn build_menu(globals: ResMut<GlobalAssets>, mut commands: Commands) {
     }, OnMenu));
 }
 
-fn on_test_play(globals: ResMut<GlobalAssets>, mut commands: Commands, mut interaction_query: Query<&Interaction, (Changed<Interaction>, With<Button>, With<TestPlayButton>)>) {
-    for (interaction) in &mut interaction_query {
+fn on_quit_game(mut interaction_query: Query<&Interaction, (Changed<Interaction>, With<Button>, With<QuitGameButton>)>, mut exit: ResMut<Events<AppExit>>) {
+    for interaction in &mut interaction_query {
         match *interaction {
             Interaction::Pressed => {
-                let load_play = MapLoadPlayResource::create_loaded(globals.test_map.clone());
-                commands.insert_resource(load_play);
+                exit.send(AppExit);
             }
             Interaction::Hovered => {
 
@@ -125,18 +148,45 @@ fn on_test_play(globals: ResMut<GlobalAssets>, mut commands: Commands, mut inter
     }
 }
 
-fn on_quit_game(mut interaction_query: Query<&Interaction, (Changed<Interaction>, With<Button>, With<QuitGameButton>, Without<TestPlayButton>)>, mut exit: ResMut<Events<AppExit>>) {
-    for interaction in &mut interaction_query {
-        match *interaction {
-            Interaction::Pressed => {
-                exit.send(AppExit);
-            }
-            Interaction::Hovered => {
+fn on_open_settings(interaction_query: Query<&Interaction, (Changed<Interaction>, With<Button>, With<OpenSettingsButton>)>, input: MenuInput, mut state: ResMut<NextState<GameState>>) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            state.set(GameState::Settings);
+        }
+    }
+    if input.just_pressed(MenuAction::OpenSettings) {
+        state.set(GameState::Settings);
+    }
+}
 
+// Replay files dropped on the window are watched on their map, if it is in the library
+fn on_replay_dropped(
+    mut drop_reader: EventReader<FileDragAndDrop>,
+    library: Res<MapLibrary>,
+    note_datas: Res<Assets<NoteData>>,
+    mut commands: Commands
+) {
+    for ev in drop_reader.read() {
+        let FileDragAndDrop::DroppedFile { path_buf, .. } = ev else {
+            continue;
+        };
+        if path_buf.extension().map_or(true, |extension| extension != REPLAY_EXTENSION) {
+            continue;
+        }
+        let replay = match Replay::load(path_buf) {
+            Ok(replay) => replay,
+            Err(err) => {
+                warn!("Could not load the replay {0}: {1}", path_buf.display(), err);
+                continue;
             }
-            Interaction::None => {
-
+        };
+        let map = library.maps.iter().find(|map| note_datas.get(&map.notes).map_or(false, |notes| map_id(notes) == replay.map_id));
+        match map {
+            Some(map) => {
+                info!("Watching the replay {0}", path_buf.display());
+                commands.insert_resource(MapLoadPlayResource::create_replay(map.clone(), replay));
             }
+            None => warn!("The map of the replay {0} ({1}) is not in the library", path_buf.display(), replay.map_title),
         }
     }
 }
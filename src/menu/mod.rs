@@ -1 +1,4 @@
-pub (crate) mod menu_state;
\ No newline at end of file
+pub (crate) mod menu_state;
+pub (crate) mod song_select;
+pub (crate) mod settings_state;
+mod modifier_select;
\ No newline at end of file
@@ -0,0 +1,211 @@
+use bevy::{ecs::{bundle::Bundle, component::Component, query::{Changed, With, Without}, system::{Query, Res, ResMut, Resource}}, hierarchy::{BuildChildren, ChildBuilder}, render::color::Color, text::{Text, TextStyle}, ui::{node_bundles::{ButtonBundle, NodeBundle, TextBundle}, widget::Button, AlignItems, BackgroundColor, BorderColor, FlexDirection, Interaction, JustifyContent, Style, UiRect, Val}, utils::default};
+
+use crate::{input::{MenuAction, MenuInput}, modifiers::{Modifier, Modifiers, MODIFIERS}};
+
+const MODIFIER_COLOR: Color = Color::rgb(0.1, 0.1, 0.12);
+const MODIFIER_ENABLED_COLOR: Color = Color::rgb(0.5, 0.05, 0.7);
+const MODIFIER_FOCUSED_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+// The modifiers and Keep pitch
+const FOCUSABLE_COUNT: usize = MODIFIERS.len() + 1;
+
+#[derive(Component)]
+pub struct ModifierButton {
+    modifier: Modifier,
+}
+
+#[derive(Component)]
+pub struct ScoreMultiplierText;
+
+// Changes the speed by this many steps
+#[derive(Component)]
+pub struct SpeedButton {
+    steps: i32,
+}
+
+#[derive(Component)]
+pub struct SpeedText;
+
+#[derive(Component)]
+pub struct PitchCorrectButton;
+
+// Position of a button in the keyboard and controller focus order
+#[derive(Component)]
+pub struct FocusIndex(usize);
+
+// The button picked with the keyboard or a controller, none until Left or Right is pressed
+#[derive(Resource, Default)]
+pub struct ModifierFocus {
+    focused: Option<usize>,
+}
+
+pub fn build_modifier_select(builder: &mut ChildBuilder) {
+    builder.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            margin: UiRect::px(10., 10., 10., 0.),
+            ..default()
+        },
+        ..default()
+    }).with_children(|builder| {
+        for (i, modifier) in MODIFIERS.into_iter().enumerate() {
+            build_modifier_button(builder, (ModifierButton { modifier }, FocusIndex(i)), modifier.name(), 130.);
+        }
+        builder.spawn((TextBundle {
+            style: Style {
+                margin: UiRect::px(10., 0., 0., 0.),
+                ..default()
+            },
+            text: Text::from_section("", TextStyle {
+                font_size: 18.,
+                color: Color::rgb(0.7, 0.7, 0.7),
+                ..default()
+            }),
+            ..default()
+        }, ScoreMultiplierText));
+    });
+
+    builder.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            margin: UiRect::px(10., 10., 10., 0.),
+            ..default()
+        },
+        ..default()
+    }).with_children(|builder| {
+        build_modifier_button(builder, SpeedButton { steps: -1 }, "-", 40.);
+        builder.spawn((TextBundle {
+            style: Style {
+                margin: UiRect::px(10., 10., 0., 0.),
+                ..default()
+            },
+            text: Text::from_section("", TextStyle {
+                font_size: 18.,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            }),
+            ..default()
+        }, SpeedText));
+        build_modifier_button(builder, SpeedButton { steps: 1 }, "+", 40.);
+        build_modifier_button(builder, (PitchCorrectButton, FocusIndex(MODIFIERS.len())), "Keep pitch", 130.);
+    });
+}
+
+fn build_modifier_button(builder: &mut ChildBuilder, marker: impl Bundle, label: &str, width: f32) {
+    builder.spawn((ButtonBundle {
+        style: Style {
+            width: Val::Px(width),
+            height: Val::Px(40.),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::px(5., 5., 0., 0.),
+            border: UiRect::all(Val::Px(2.)),
+            ..default()
+        },
+        background_color: BackgroundColor(MODIFIER_COLOR),
+        border_color: BorderColor(Color::NONE),
+        ..default()
+    }, marker)).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            label,
+            TextStyle {
+                font_size: 18.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            },
+        ));
+    });
+}
+
+pub fn on_modifier_click(
+    mut modifiers: ResMut<Modifiers>,
+    interaction_query: Query<(&Interaction, &ModifierButton), (Changed<Interaction>, With<Button>)>,
+    speed_query: Query<(&Interaction, &SpeedButton), (Changed<Interaction>, With<Button>)>,
+    pitch_query: Query<&Interaction, (Changed<Interaction>, With<Button>, With<PitchCorrectButton>)>
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            modifiers.toggle(button.modifier);
+        }
+    }
+    for (interaction, button) in &speed_query {
+        if *interaction == Interaction::Pressed {
+            modifiers.change_speed(button.steps);
+        }
+    }
+    for interaction in &pitch_query {
+        if *interaction == Interaction::Pressed {
+            modifiers.pitch_correct = !modifiers.pitch_correct;
+        }
+    }
+}
+
+// Left and Right move the focus along the modifiers, the speed has its own keys
+pub fn on_modifier_keys(mut modifiers: ResMut<Modifiers>, mut focus: ResMut<ModifierFocus>, input: MenuInput) {
+    if input.just_pressed(MenuAction::Right) {
+        focus.focused = Some(focus.focused.map_or(0, |i| (i + 1) % FOCUSABLE_COUNT));
+    }
+    if input.just_pressed(MenuAction::Left) {
+        focus.focused = Some(focus.focused.map_or(FOCUSABLE_COUNT - 1, |i| (i + FOCUSABLE_COUNT - 1) % FOCUSABLE_COUNT));
+    }
+    if input.just_pressed(MenuAction::ToggleModifier) {
+        match focus.focused {
+            Some(i) if i < MODIFIERS.len() => modifiers.toggle(MODIFIERS[i]),
+            Some(_) => modifiers.pitch_correct = !modifiers.pitch_correct,
+            None => {}
+        }
+    }
+    let speed_steps = input.just_pressed(MenuAction::SpeedUp) as i32 - input.just_pressed(MenuAction::SpeedDown) as i32;
+    if speed_steps != 0 {
+        modifiers.change_speed(speed_steps);
+    }
+}
+
+pub fn update_modifier_select(
+    modifiers: Res<Modifiers>,
+    focus: Res<ModifierFocus>,
+    mut q_focusable: Query<(&FocusIndex, &mut BorderColor)>,
+    mut q_buttons: Query<(&ModifierButton, &mut BackgroundColor)>,
+    mut q_pitch: Query<&mut BackgroundColor, (With<PitchCorrectButton>, Without<ModifierButton>)>,
+    mut q_multiplier: Query<&mut Text, (With<ScoreMultiplierText>, Without<SpeedText>)>,
+    mut q_speed: Query<&mut Text, With<SpeedText>>
+) {
+    for (button, mut background) in &mut q_buttons {
+        *background = if modifiers.is_enabled(button.modifier) {
+            BackgroundColor(MODIFIER_ENABLED_COLOR)
+        } else {
+            BackgroundColor(MODIFIER_COLOR)
+        };
+    }
+    for (index, mut border) in &mut q_focusable {
+        *border = if focus.focused == Some(index.0) {
+            BorderColor(MODIFIER_FOCUSED_COLOR)
+        } else {
+            BorderColor(Color::NONE)
+        };
+    }
+    for mut background in &mut q_pitch {
+        *background = if modifiers.pitch_correct {
+            BackgroundColor(MODIFIER_ENABLED_COLOR)
+        } else {
+            BackgroundColor(MODIFIER_COLOR)
+        };
+    }
+    if let Ok(mut text) = q_speed.get_single_mut() {
+        let value = format!("Speed {:.1}x", modifiers.speed);
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+    if let Ok(mut text) = q_multiplier.get_single_mut() {
+        let value = if modifiers.autoplay {
+            "Autoplay, not saved".to_owned()
+        } else {
+            format!("Score x{:.2}", modifiers.score_multiplier())
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
@@ -0,0 +1,457 @@
+use bevy::{app::{App, Plugin, Update}, core_pipeline::core_2d::Camera2dBundle, ecs::{component::Component, entity::Entity, query::{Changed, With}, schedule::{common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit}, system::{Commands, Query, Res, ResMut, Resource}}, hierarchy::{BuildChildren, ChildBuilder, DespawnRecursiveExt}, render::{camera::{Camera, ClearColorConfig}, color::Color}, text::{Text, TextStyle}, ui::{node_bundles::{ButtonBundle, NodeBundle, TextBundle}, widget::Button, AlignContent, AlignItems, BackgroundColor, FlexDirection, FlexWrap, Interaction, JustifyContent, Style, UiRect, Val}, utils::default};
+
+use crate::{engine::{autoplay::{AutoplayCurve, AUTOPLAY_CURVES}, judgement::{MIN_JUDGEMENT_WINDOW, NOTE_LATE_HIT_WINDOW}}, input::{MenuAction, MenuInput}, settings::{CursorArea, CursorEdge, CursorMode, Settings, NOTE_PALETTES}, state::GameState};
+
+const ROW_COLOR: Color = Color::rgb(0.1, 0.1, 0.12);
+const ROW_SELECTED_COLOR: Color = Color::rgb(0.3, 0.05, 0.4);
+const BUTTON_COLOR: Color = Color::rgb(0.2, 0.2, 0.2);
+
+pub struct SettingsStatePlugin;
+
+#[derive(Component)]
+pub struct OnSettings;
+
+// Every setting that can be changed in the settings screen, in display order
+#[derive(Clone, Copy, PartialEq)]
+pub enum SettingId {
+    ApproachRate,
+    ApproachDistance,
+    FadeIn,
+    StartFinishWait,
+    PerfectWindow,
+    GreatWindow,
+    Sensitivity,
+    CursorMode,
+    CursorEdge,
+    CursorArea,
+    StickDeadzone,
+    StickSpeed,
+    StickAcceleration,
+    AutoplayCurve,
+    MasterVolume,
+    MusicVolume,
+    HitVolume,
+    NotePalette,
+    Fov
+}
+
+pub const SETTING_IDS: [SettingId; 19] = [
+    SettingId::ApproachRate,
+    SettingId::ApproachDistance,
+    SettingId::FadeIn,
+    SettingId::StartFinishWait,
+    SettingId::PerfectWindow,
+    SettingId::GreatWindow,
+    SettingId::Sensitivity,
+    SettingId::CursorMode,
+    SettingId::CursorEdge,
+    SettingId::CursorArea,
+    SettingId::StickDeadzone,
+    SettingId::StickSpeed,
+    SettingId::StickAcceleration,
+    SettingId::AutoplayCurve,
+    SettingId::MasterVolume,
+    SettingId::MusicVolume,
+    SettingId::HitVolume,
+    SettingId::NotePalette,
+    SettingId::Fov
+];
+
+impl SettingId {
+    fn label(&self) -> &'static str {
+        match self {
+            SettingId::ApproachRate => "Approach rate",
+            SettingId::ApproachDistance => "Approach distance",
+            SettingId::FadeIn => "Fade in",
+            SettingId::StartFinishWait => "Start/finish wait",
+            SettingId::PerfectWindow => "Perfect window",
+            SettingId::GreatWindow => "Great window",
+            SettingId::Sensitivity => "Sensitivity",
+            SettingId::CursorMode => "Cursor mode",
+            SettingId::CursorEdge => "Cursor edge",
+            SettingId::CursorArea => "Cursor area",
+            SettingId::StickDeadzone => "Stick deadzone",
+            SettingId::StickSpeed => "Stick speed",
+            SettingId::StickAcceleration => "Stick acceleration",
+            SettingId::AutoplayCurve => "Autoplay movement",
+            SettingId::MasterVolume => "Master volume",
+            SettingId::MusicVolume => "Music volume",
+            SettingId::HitVolume => "Hit sound volume",
+            SettingId::NotePalette => "Note colors",
+            SettingId::Fov => "Field of view",
+        }
+    }
+
+    fn value(&self, settings: &Settings) -> String {
+        match self {
+            SettingId::ApproachRate => format!("{0}ms", settings.approach_rate),
+            SettingId::ApproachDistance => format!("{:.1}", settings.approach_distance),
+            SettingId::FadeIn => format!("{0}ms", settings.fade_in),
+            SettingId::StartFinishWait => format!("{0}ms", settings.start_finish_wait),
+            SettingId::PerfectWindow => format!("{0}ms", settings.perfect_window),
+            SettingId::GreatWindow => format!("{0}ms", settings.great_window),
+            SettingId::Sensitivity => format!("{:.2}x", settings.sensitivity),
+            SettingId::CursorMode => match settings.cursor_mode {
+                CursorMode::Relative => "Relative",
+                CursorMode::Absolute => "Absolute",
+                CursorMode::Keyboard => "Keyboard",
+            }.to_owned(),
+            SettingId::CursorEdge => match settings.cursor_edge {
+                CursorEdge::Border => "Hard border",
+                CursorEdge::Drift => "Drift",
+            }.to_owned(),
+            SettingId::CursorArea => match settings.cursor_area {
+                CursorArea::Grid => "Grid",
+                CursorArea::Camera => "Camera",
+            }.to_owned(),
+            SettingId::StickDeadzone => format!("{:.0}%", settings.stick_deadzone * 100.),
+            SettingId::StickSpeed => format!("{:.1}", settings.stick_speed),
+            SettingId::StickAcceleration => format!("{:.1}", settings.stick_acceleration),
+            SettingId::AutoplayCurve => match settings.autoplay_curve {
+                AutoplayCurve::Linear => "Linear",
+                AutoplayCurve::Eased => "Eased",
+                AutoplayCurve::Flick => "Flick",
+            }.to_owned(),
+            SettingId::MasterVolume => format_volume(settings.master_volume),
+            SettingId::MusicVolume => format_volume(settings.music_volume),
+            SettingId::HitVolume => format_volume(settings.hit_volume),
+            SettingId::NotePalette => match settings.note_palette_preset() {
+                Some(preset) => NOTE_PALETTES[preset].0.to_owned(),
+                None => "Custom".to_owned(),
+            },
+            SettingId::Fov => format!("{:.0}", settings.fov),
+        }
+    }
+
+    fn change(&self, settings: &mut Settings, steps: i32) {
+        match self {
+            SettingId::ApproachRate => settings.approach_rate = (settings.approach_rate + steps as i128 * 25).clamp(100, 3000),
+            SettingId::ApproachDistance => settings.approach_distance = (settings.approach_distance + steps as f32 * 0.5).clamp(1., 100.),
+            SettingId::FadeIn => settings.fade_in = (settings.fade_in + steps as i128 * 25).clamp(0, 3000),
+            SettingId::StartFinishWait => settings.start_finish_wait = (settings.start_finish_wait + steps as i128 * 100).clamp(0, 5000),
+            // Perfect is always inside great, and great inside the hit window
+            SettingId::PerfectWindow => settings.perfect_window = (settings.perfect_window + steps as i128 * 5).min(settings.great_window).max(MIN_JUDGEMENT_WINDOW),
+            SettingId::GreatWindow => settings.great_window = (settings.great_window + steps as i128 * 5).max(settings.perfect_window).min(NOTE_LATE_HIT_WINDOW),
+            SettingId::Sensitivity => settings.sensitivity = (settings.sensitivity + steps as f32 * 0.05).clamp(0.05, 10.),
+            SettingId::CursorMode => {
+                let index = CURSOR_MODES.iter().position(|mode| *mode == settings.cursor_mode).unwrap_or(0) as i32;
+                settings.cursor_mode = CURSOR_MODES[(index + steps).rem_euclid(CURSOR_MODES.len() as i32) as usize];
+            }
+            // Only two choices, any change switches to the other one
+            SettingId::CursorEdge => settings.cursor_edge = match settings.cursor_edge {
+                CursorEdge::Border => CursorEdge::Drift,
+                CursorEdge::Drift => CursorEdge::Border,
+            },
+            SettingId::CursorArea => settings.cursor_area = match settings.cursor_area {
+                CursorArea::Grid => CursorArea::Camera,
+                CursorArea::Camera => CursorArea::Grid,
+            },
+            SettingId::StickDeadzone => settings.stick_deadzone = ((settings.stick_deadzone * 20.).round() + steps as f32).clamp(0., 18.) / 20.,
+            SettingId::StickSpeed => settings.stick_speed = (settings.stick_speed + steps as f32 * 0.5).clamp(0.5, 20.),
+            SettingId::StickAcceleration => settings.stick_acceleration = (settings.stick_acceleration + steps as f32 * 0.1).clamp(1., 4.),
+            SettingId::AutoplayCurve => {
+                let index = AUTOPLAY_CURVES.iter().position(|curve| *curve == settings.autoplay_curve).unwrap_or(0) as i32;
+                settings.autoplay_curve = AUTOPLAY_CURVES[(index + steps).rem_euclid(AUTOPLAY_CURVES.len() as i32) as usize];
+            }
+            SettingId::MasterVolume => settings.master_volume = change_volume(settings.master_volume, steps),
+            SettingId::MusicVolume => settings.music_volume = change_volume(settings.music_volume, steps),
+            SettingId::HitVolume => settings.hit_volume = change_volume(settings.hit_volume, steps),
+            SettingId::NotePalette => {
+                // A custom palette is replaced by the first preset
+                let preset = match settings.note_palette_preset() {
+                    Some(preset) => (preset as i32 + steps).rem_euclid(NOTE_PALETTES.len() as i32) as usize,
+                    None => 0,
+                };
+                settings.note_palette = NOTE_PALETTES[preset].1.iter().map(|color| color.to_string()).collect();
+            }
+            SettingId::Fov => settings.fov = (settings.fov + steps as f32 * 5.).clamp(40., 120.),
+        }
+    }
+}
+
+const SETTING_ROWS_PER_COLUMN: usize = 10;
+
+const CURSOR_MODES: [CursorMode; 3] = [CursorMode::Relative, CursorMode::Absolute, CursorMode::Keyboard];
+
+fn format_volume(volume: f32) -> String {
+    return format!("{:.0}%", volume * 100.);
+}
+
+fn change_volume(volume: f32, steps: i32) -> f32 {
+    return ((volume * 20.).round() + steps as f32).clamp(0., 20.) / 20.;
+}
+
+// The row selected with the keyboard
+#[derive(Resource, Default)]
+pub struct SettingsSelection {
+    selected: usize,
+}
+
+#[derive(Component)]
+pub struct SettingRow {
+    id: SettingId,
+}
+
+#[derive(Component)]
+pub struct SettingValueText {
+    id: SettingId,
+}
+
+#[derive(Component)]
+pub struct SettingChangeButton {
+    id: SettingId,
+    steps: i32,
+}
+
+#[derive(Component, Clone, Copy)]
+pub enum SettingsButton {
+    Reset,
+    Back
+}
+
+impl Plugin for SettingsStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Settings), build_settings);
+        app.add_systems(OnExit(GameState::Settings), cleanup_settings);
+        app.init_resource::<SettingsSelection>();
+        app.add_systems(Update, (
+            on_setting_change_click,
+            on_settings_button,
+            on_settings_keys,
+            update_settings
+        ).chain().run_if(in_state(GameState::Settings)));
+    }
+}
+
+fn build_settings(settings: Res<Settings>, mut commands: Commands) {
+    commands.spawn((NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        ..default()
+    }, OnSettings)).with_children(|builder| {
+        builder.spawn(TextBundle {
+            style: Style {
+                margin: UiRect::px(10., 10., 10., 20.),
+                ..default()
+            },
+            text: Text::from_section("Settings", TextStyle {
+                font_size: 36.,
+                color: Color::rgb(1., 1., 1.),
+                ..default()
+            }),
+            ..default()
+        });
+        // Rows wrap into two columns so every setting fits on screen
+        builder.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                flex_wrap: FlexWrap::Wrap,
+                height: Val::Px(40. * SETTING_ROWS_PER_COLUMN as f32),
+                align_content: AlignContent::Center,
+                ..default()
+            },
+            ..default()
+        }).with_children(|builder| {
+            for id in SETTING_IDS {
+                build_setting_row(builder, id, &settings);
+            }
+        });
+        builder.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                margin: UiRect::px(10., 10., 20., 10.),
+                ..default()
+            },
+            ..default()
+        }).with_children(|builder| {
+            build_settings_button(builder, SettingsButton::Reset, "Reset to defaults");
+            build_settings_button(builder, SettingsButton::Back, "Back (Esc)");
+        });
+    });
+
+    commands.spawn((Camera2dBundle {
+        camera: Camera {
+            clear_color: ClearColorConfig::Custom(Color::rgb(0., 0., 0.)),
+            ..default()
+        },
+        ..default()
+    }, OnSettings));
+}
+
+fn build_setting_row(builder: &mut ChildBuilder, id: SettingId, settings: &Settings) {
+    builder.spawn((NodeBundle {
+        style: Style {
+            width: Val::Px(520.),
+            height: Val::Px(36.),
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::SpaceBetween,
+            padding: UiRect::px(15., 10., 0., 0.),
+            margin: UiRect::px(5., 5., 2., 2.),
+            ..default()
+        },
+        background_color: BackgroundColor(ROW_COLOR),
+        ..default()
+    }, SettingRow { id })).with_children(|builder| {
+        builder.spawn(TextBundle::from_section(id.label(), TextStyle {
+            font_size: 22.,
+            color: Color::rgb(0.9, 0.9, 0.9),
+            ..default()
+        }));
+        builder.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        }).with_children(|builder| {
+            build_change_button(builder, id, -1, "-");
+            builder.spawn((TextBundle {
+                style: Style {
+                    width: Val::Px(120.),
+                    justify_content: JustifyContent::Center,
+                    margin: UiRect::px(10., 10., 0., 0.),
+                    ..default()
+                },
+                text: Text::from_section(id.value(settings), TextStyle {
+                    font_size: 22.,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                }).with_justify(bevy::text::JustifyText::Center),
+                ..default()
+            }, SettingValueText { id }));
+            build_change_button(builder, id, 1, "+");
+        });
+    });
+}
+
+fn build_change_button(builder: &mut ChildBuilder, id: SettingId, steps: i32, label: &str) {
+    builder.spawn((ButtonBundle {
+        style: Style {
+            width: Val::Px(32.),
+            height: Val::Px(32.),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: BackgroundColor(BUTTON_COLOR),
+        ..default()
+    }, SettingChangeButton { id, steps })).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(label, TextStyle {
+            font_size: 24.,
+            color: Color::rgb(0.9, 0.9, 0.9),
+            ..default()
+        }));
+    });
+}
+
+fn build_settings_button(builder: &mut ChildBuilder, button: SettingsButton, label: &str) {
+    builder.spawn((ButtonBundle {
+        style: Style {
+            width: Val::Px(250.),
+            height: Val::Px(60.),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::px(10., 10., 10., 10.),
+            ..default()
+        },
+        background_color: BackgroundColor(Color::rgb(0.15, 0.15, 0.15)),
+        ..default()
+    }, button)).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(label, TextStyle {
+            font_size: 24.,
+            color: Color::rgb(0.9, 0.9, 0.9),
+            ..default()
+        }));
+    });
+}
+
+fn on_setting_change_click(
+    mut settings: ResMut<Settings>,
+    mut selection: ResMut<SettingsSelection>,
+    interaction_query: Query<(&Interaction, &SettingChangeButton), (Changed<Interaction>, With<Button>)>
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            button.id.change(&mut settings, button.steps);
+            selection.selected = SETTING_IDS.iter().position(|id| *id == button.id).unwrap_or(0);
+        }
+    }
+}
+
+fn on_settings_button(
+    mut settings: ResMut<Settings>,
+    interaction_query: Query<(&Interaction, &SettingsButton), (Changed<Interaction>, With<Button>)>,
+    mut state: ResMut<NextState<GameState>>
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            SettingsButton::Reset => settings.reset(),
+            SettingsButton::Back => state.set(GameState::Menu),
+        }
+    }
+}
+
+fn on_settings_keys(
+    input: MenuInput,
+    mut settings: ResMut<Settings>,
+    mut selection: ResMut<SettingsSelection>,
+    mut state: ResMut<NextState<GameState>>
+) {
+    if input.just_pressed(MenuAction::Back) {
+        state.set(GameState::Menu);
+        return;
+    }
+    if input.just_pressed(MenuAction::Down) {
+        selection.selected = (selection.selected + 1).min(SETTING_IDS.len() - 1);
+    }
+    if input.just_pressed(MenuAction::Up) {
+        selection.selected = selection.selected.saturating_sub(1);
+    }
+    let id = SETTING_IDS[selection.selected];
+    if input.just_pressed(MenuAction::Right) {
+        id.change(&mut settings, 1);
+    }
+    if input.just_pressed(MenuAction::Left) {
+        id.change(&mut settings, -1);
+    }
+}
+
+fn update_settings(
+    settings: Res<Settings>,
+    selection: Res<SettingsSelection>,
+    mut q_rows: Query<(&SettingRow, &mut BackgroundColor)>,
+    mut q_values: Query<(&SettingValueText, &mut Text)>
+) {
+    for (row, mut background) in &mut q_rows {
+        *background = if row.id == SETTING_IDS[selection.selected] {
+            BackgroundColor(ROW_SELECTED_COLOR)
+        } else {
+            BackgroundColor(ROW_COLOR)
+        };
+    }
+    for (value, mut text) in &mut q_values {
+        let new_value = value.id.value(&settings);
+        if text.sections[0].value != new_value {
+            text.sections[0].value = new_value;
+        }
+    }
+}
+
+// Settings are saved when leaving the screen
+fn cleanup_settings(settings: Res<Settings>, mut commands: Commands, query: Query<Entity, With<OnSettings>>) {
+    settings.save();
+    for ent in query.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+}
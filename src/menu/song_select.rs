@@ -0,0 +1,482 @@
+use std::time::Duration;
+
+use bevy::{asset::{AssetId, AssetServer, Assets, Handle}, ecs::{change_detection::DetectChanges, component::Component, event::EventReader, query::{Changed, With, Without}, system::{Commands, Local, Query, Res, ResMut, Resource}}, hierarchy::{BuildChildren, ChildBuilder}, input::mouse::{MouseScrollUnit, MouseWheel}, log::warn, render::color::Color, text::{Text, TextSection, TextStyle}, ui::{node_bundles::{ButtonBundle, ImageBundle, NodeBundle, TextBundle}, widget::Button, AlignItems, BackgroundColor, Display, FlexDirection, Interaction, JustifyContent, Node, Overflow, Style, UiImage, UiRect, Val}, utils::default};
+use bevy_kira_audio::prelude::*;
+
+use crate::{input::{MenuAction, MenuInput}, library::MapLibrary, map::{format_length, Map, NoteData}, modifiers::Modifiers, play::play_state::MapLoadPlayResource, replay::{replays_dir, Replay}, scores::{format_score_line, map_id, ScoreDatabase}, settings::Settings};
+
+const ENTRY_HEIGHT: f32 = 64.;
+const ENTRY_MARGIN: f32 = 4.;
+const SCROLL_LINE_HEIGHT: f32 = 20.;
+const PAGE_SIZE: usize = 8;
+const PREVIEW_FADE_MS: u64 = 500;
+const TOP_SCORES_SHOWN: usize = 5;
+
+const ENTRY_COLOR: Color = Color::rgb(0.1, 0.1, 0.12);
+const ENTRY_HOVERED_COLOR: Color = Color::rgb(0.2, 0.1, 0.25);
+const ENTRY_SELECTED_COLOR: Color = Color::rgb(0.5, 0.05, 0.7);
+
+// The index of the selected map in the library, kept between visits of the menu.
+#[derive(Resource, Default)]
+pub struct SongSelection {
+    pub selected: usize,
+}
+
+// The song of the selected map playing in the background.
+#[derive(Resource, Default)]
+pub struct SongPreview {
+    audio_path: String,
+    audio: Handle<AudioSource>,
+    instance: Handle<AudioInstance>,
+}
+
+#[derive(Component)]
+pub struct SongListPanel;
+
+#[derive(Component, Default)]
+pub struct SongList {
+    position: f32,
+}
+
+#[derive(Component)]
+pub struct SongEntry {
+    index: usize,
+}
+
+#[derive(Component)]
+pub struct SongEntryTitleText {
+    index: usize,
+}
+
+#[derive(Component)]
+pub struct SongEntryStatsText {
+    index: usize,
+}
+
+#[derive(Component)]
+pub struct SongDetailsText;
+
+#[derive(Component)]
+pub struct SongCoverImage;
+
+#[derive(Component)]
+pub struct PlaySelectedButton;
+
+pub fn build_song_select(builder: &mut ChildBuilder, library: &MapLibrary) {
+    builder.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(90.),
+            height: Val::Percent(65.),
+            flex_direction: FlexDirection::Row,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        ..default()
+    }).with_children(|builder| {
+        // Scrollable song list
+        builder.spawn((NodeBundle {
+            style: Style {
+                width: Val::Percent(60.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                overflow: Overflow::clip_y(),
+                margin: UiRect::px(10., 10., 0., 0.),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgb(0.05, 0.05, 0.05)),
+            ..default()
+        }, SongListPanel)).with_children(|builder| {
+            builder.spawn((NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    width: Val::Percent(100.),
+                    ..default()
+                },
+                ..default()
+            }, SongList::default())).with_children(|builder| {
+                for (index, map) in library.maps.iter().enumerate() {
+                    build_song_entry(builder, index, map);
+                }
+            });
+        });
+
+        // Details of the selected map
+        builder.spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(40.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::SpaceBetween,
+                margin: UiRect::px(10., 10., 0., 0.),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgb(0.05, 0.05, 0.05)),
+            ..default()
+        }).with_children(|builder| {
+            builder.spawn((ImageBundle {
+                style: Style {
+                    width: Val::Px(160.),
+                    height: Val::Px(160.),
+                    margin: UiRect::px(15., 15., 15., 0.),
+                    display: Display::None,
+                    ..default()
+                },
+                ..default()
+            }, SongCoverImage));
+            builder.spawn((TextBundle {
+                style: Style {
+                    margin: UiRect::all(Val::Px(15.)),
+                    ..default()
+                },
+                text: Text::from_section("No maps found", TextStyle {
+                    font_size: 22.,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                    ..default()
+                }),
+                ..default()
+            }, SongDetailsText));
+            builder.spawn((ButtonBundle {
+                style: Style {
+                    width: Val::Px(250.),
+                    height: Val::Px(60.),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    margin: UiRect::all(Val::Px(15.)),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgb(0.5, 0.05, 0.7)),
+                ..default()
+            }, PlaySelectedButton)).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "Play",
+                    TextStyle {
+                        font_size: 30.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..default()
+                    },
+                ));
+            });
+        });
+    });
+}
+
+fn build_song_entry(builder: &mut ChildBuilder, index: usize, map: &Map) {
+    builder.spawn((ButtonBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Px(ENTRY_HEIGHT),
+            flex_shrink: 0.,
+            flex_direction: FlexDirection::Row,
+            justify_content: JustifyContent::SpaceBetween,
+            align_items: AlignItems::Center,
+            padding: UiRect::px(10., 10., 0., 0.),
+            margin: UiRect::px(0., 0., ENTRY_MARGIN / 2., ENTRY_MARGIN / 2.),
+            ..default()
+        },
+        background_color: BackgroundColor(ENTRY_COLOR),
+        ..default()
+    }, SongEntry { index })).with_children(|parent| {
+        parent.spawn((TextBundle::from_sections([
+            TextSection::new(map.title.clone(), TextStyle {
+                font_size: 22.,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            }),
+            TextSection::new(entry_subtitle(map), TextStyle {
+                font_size: 16.,
+                color: Color::rgb(0.6, 0.6, 0.6),
+                ..default()
+            }),
+        ]), SongEntryTitleText { index }));
+        parent.spawn((TextBundle::from_section("", TextStyle {
+            font_size: 16.,
+            color: Color::rgb(0.6, 0.6, 0.6),
+            ..default()
+        }), SongEntryStatsText { index }));
+    });
+}
+
+pub fn on_song_entry_click(
+    library: Res<MapLibrary>,
+    mut selection: ResMut<SongSelection>,
+    modifiers: Res<Modifiers>,
+    mut commands: Commands,
+    interaction_query: Query<(&Interaction, &SongEntry), (Changed<Interaction>, With<Button>)>
+) {
+    for (interaction, entry) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        // Clicking the selected map a second time plays it
+        if selection.selected == entry.index {
+            play_selected(&library, &selection, &modifiers, &mut commands);
+        } else {
+            selection.selected = entry.index;
+        }
+    }
+}
+
+pub fn on_play_selected(
+    library: Res<MapLibrary>,
+    selection: Res<SongSelection>,
+    modifiers: Res<Modifiers>,
+    mut commands: Commands,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<Button>, With<PlaySelectedButton>)>
+) {
+    for interaction in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            play_selected(&library, &selection, &modifiers, &mut commands);
+        }
+    }
+}
+
+pub fn on_song_select_keys(
+    library: Res<MapLibrary>,
+    mut selection: ResMut<SongSelection>,
+    modifiers: Res<Modifiers>,
+    note_datas: Res<Assets<NoteData>>,
+    db: Res<ScoreDatabase>,
+    input: MenuInput,
+    mut commands: Commands
+) {
+    if library.maps.is_empty() {
+        return;
+    }
+    let last = library.maps.len() - 1;
+    if input.just_pressed(MenuAction::Down) {
+        selection.selected = (selection.selected + 1).min(last);
+    }
+    if input.just_pressed(MenuAction::Up) {
+        selection.selected = selection.selected.saturating_sub(1);
+    }
+    if input.just_pressed(MenuAction::PageDown) {
+        selection.selected = (selection.selected + PAGE_SIZE).min(last);
+    }
+    if input.just_pressed(MenuAction::PageUp) {
+        selection.selected = selection.selected.saturating_sub(PAGE_SIZE);
+    }
+    if input.just_pressed(MenuAction::First) {
+        selection.selected = 0;
+    }
+    if input.just_pressed(MenuAction::Last) {
+        selection.selected = last;
+    }
+    if input.just_pressed(MenuAction::Confirm) {
+        play_selected(&library, &selection, &modifiers, &mut commands);
+    }
+    if input.just_pressed(MenuAction::Watch) {
+        watch_best_replay(&library, &selection, &note_datas, &db, &mut commands);
+    }
+}
+
+pub fn on_song_list_scroll(
+    mut wheel_reader: EventReader<MouseWheel>,
+    mut q_list: Query<(&mut SongList, &mut Style, &Node)>,
+    q_panel: Query<&Node, (With<SongListPanel>, Without<SongList>)>
+) {
+    let Ok(panel) = q_panel.get_single() else {
+        return;
+    };
+    for ev in wheel_reader.read() {
+        let delta = match ev.unit {
+            MouseScrollUnit::Line => ev.y * SCROLL_LINE_HEIGHT,
+            MouseScrollUnit::Pixel => ev.y,
+        };
+        for (mut list, mut style, node) in &mut q_list {
+            let max_scroll = (node.size().y - panel.size().y).max(0.);
+            list.position = (list.position - delta).clamp(0., max_scroll);
+            style.top = Val::Px(-list.position);
+        }
+    }
+}
+
+// Keeps the list, entry stats and the details panel in sync with the selection and the loaded note data.
+pub fn update_song_select(
+    library: Res<MapLibrary>,
+    mut selection: ResMut<SongSelection>,
+    note_datas: Res<Assets<NoteData>>,
+    db: Res<ScoreDatabase>,
+    mut selected_map_id: Local<Option<(AssetId<NoteData>, String)>>,
+    mut q_entries: Query<(&SongEntry, &Interaction, &mut BackgroundColor)>,
+    mut q_entry_titles: Query<(&SongEntryTitleText, &mut Text), (Without<SongEntryStatsText>, Without<SongDetailsText>)>,
+    mut q_entry_stats: Query<(&SongEntryStatsText, &mut Text), Without<SongDetailsText>>,
+    mut q_details: Query<&mut Text, With<SongDetailsText>>,
+    mut q_cover: Query<(&mut UiImage, &mut Style), (With<SongCoverImage>, Without<SongList>)>,
+    mut q_list: Query<(&mut SongList, &mut Style)>,
+    q_panel: Query<&Node, With<SongListPanel>>
+) {
+    if library.maps.is_empty() {
+        return;
+    }
+    if selection.selected >= library.maps.len() {
+        selection.selected = library.maps.len() - 1;
+    }
+
+    for (entry, interaction, mut background) in &mut q_entries {
+        *background = if entry.index == selection.selected {
+            BackgroundColor(ENTRY_SELECTED_COLOR)
+        } else if *interaction == Interaction::Hovered {
+            BackgroundColor(ENTRY_HOVERED_COLOR)
+        } else {
+            BackgroundColor(ENTRY_COLOR)
+        };
+    }
+
+    // Titles can change once the metadata of a map is loaded
+    for (title, mut text) in &mut q_entry_titles {
+        let map = &library.maps[title.index];
+        let subtitle = entry_subtitle(map);
+        if text.sections[0].value != map.title || text.sections[1].value != subtitle {
+            text.sections[0].value = map.title.clone();
+            text.sections[1].value = subtitle;
+        }
+    }
+
+    for (stats, mut text) in &mut q_entry_stats {
+        let value = match note_datas.get(&library.maps[stats.index].notes) {
+            Some(notes) => notes.0.len().to_string() + " notes\n" + &format_length(notes.length_ms()),
+            None => "loading...".to_owned(),
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+
+    let map = &library.maps[selection.selected];
+    if let Ok(mut details) = q_details.get_single_mut() {
+        let stats = match note_datas.get(&map.notes) {
+            Some(notes) => {
+                // Hashing the notes every frame would be wasteful
+                if selected_map_id.as_ref().map_or(true, |(id, _)| *id != map.notes.id()) {
+                    *selected_map_id = Some((map.notes.id(), map_id(notes)));
+                }
+                let map_id = &selected_map_id.as_ref().unwrap().1;
+                format!("Notes: {0}\nLength: {1}\n{2}", notes.0.len(), format_length(notes.length_ms()), build_local_scores(&db, map_id))
+            }
+            None => "Notes: ...\nLength: ...".to_owned(),
+        };
+        let value = format!("{0}\n\nArtist: {1}\nMapper: {2}\nDifficulty: {3}\n{4}", map.title, map.artist, map.mapper, map.difficulty, stats);
+        if details.sections[0].value != value {
+            details.sections[0].value = value;
+        }
+    }
+
+    if let Ok((mut image, mut style)) = q_cover.get_single_mut() {
+        match &map.cover {
+            Some(cover) => {
+                if image.texture != *cover {
+                    image.texture = cover.clone();
+                }
+                if style.display != Display::Flex {
+                    style.display = Display::Flex;
+                }
+            }
+            None => {
+                if style.display != Display::None {
+                    style.display = Display::None;
+                }
+            }
+        }
+    }
+
+    // Scroll so that the selected entry is always visible
+    if !selection.is_changed() {
+        return;
+    }
+    let Ok(panel) = q_panel.get_single() else {
+        return;
+    };
+    let entry_top = selection.selected as f32 * (ENTRY_HEIGHT + ENTRY_MARGIN);
+    let entry_bottom = entry_top + ENTRY_HEIGHT + ENTRY_MARGIN;
+    for (mut list, mut style) in &mut q_list {
+        if entry_top < list.position {
+            list.position = entry_top;
+        } else if entry_bottom > list.position + panel.size().y {
+            list.position = entry_bottom - panel.size().y;
+        }
+        style.top = Val::Px(-list.position);
+    }
+}
+
+pub fn update_song_preview(
+    library: Res<MapLibrary>,
+    selection: Res<SongSelection>,
+    mut preview: ResMut<SongPreview>,
+    server: Res<AssetServer>,
+    settings: Res<Settings>,
+    audio: Res<Audio>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>
+) {
+    let Some(map) = library.maps.get(selection.selected) else {
+        return;
+    };
+    // The audio path can also change once the map metadata is loaded
+    if preview.audio_path == map.audio_path {
+        return;
+    }
+    if let Some(instance) = audio_instances.get_mut(&preview.instance) {
+        instance.stop(AudioTween::default());
+    }
+    preview.audio_path = map.audio_path.clone();
+    if map.audio_path.is_empty() {
+        return;
+    }
+    preview.audio = server.load::<AudioSource>(&map.audio_path);
+    let mut command = audio.play(preview.audio.clone());
+    command.start_from(map.preview_ms.unwrap_or(0) as f64 / 1000.);
+    command.fade_in(AudioTween::new(Duration::from_millis(PREVIEW_FADE_MS), AudioEasing::OutPowi(2)));
+    command.looped();
+    command.with_volume(settings.music_volume());
+    preview.instance = command.handle().clone();
+}
+
+pub fn stop_song_preview(mut preview: ResMut<SongPreview>, mut audio_instances: ResMut<Assets<AudioInstance>>) {
+    if let Some(instance) = audio_instances.get_mut(&preview.instance) {
+        instance.stop(AudioTween::default());
+    }
+    *preview = SongPreview::default();
+}
+
+fn build_local_scores(db: &ScoreDatabase, map_id: &str) -> String {
+    let top_scores = db.top_scores(map_id, TOP_SCORES_SHOWN);
+    if top_scores.is_empty() {
+        return "\nNo local scores yet".to_owned();
+    }
+    let mut value = format!("\nPersonal best: {0}\n\nLocal scores (W to watch the best replay):", format_score_line(0, top_scores[0]));
+    for (rank, record) in top_scores.iter().enumerate() {
+        value += &("\n".to_owned() + &format_score_line(rank, record));
+    }
+    return value;
+}
+
+fn entry_subtitle(map: &Map) -> String {
+    return "\n".to_owned() + &map.artist + " // " + &map.mapper;
+}
+
+fn play_selected(library: &MapLibrary, selection: &SongSelection, modifiers: &Modifiers, commands: &mut Commands) {
+    let Some(map) = library.maps.get(selection.selected) else {
+        return;
+    };
+    commands.insert_resource(MapLoadPlayResource::create_loaded(map.clone(), modifiers.clone()));
+}
+
+// Watches the best local score on the selected map that has a replay
+fn watch_best_replay(library: &MapLibrary, selection: &SongSelection, note_datas: &Assets<NoteData>, db: &ScoreDatabase, commands: &mut Commands) {
+    let Some(map) = library.maps.get(selection.selected) else {
+        return;
+    };
+    let Some(notes) = note_datas.get(&map.notes) else {
+        return;
+    };
+    let Some(file) = db.top_scores(&map_id(notes), usize::MAX).into_iter().find_map(|record| record.replay.clone()) else {
+        return;
+    };
+    let Some(dir) = replays_dir() else {
+        return;
+    };
+    match Replay::load(&dir.join(&file)) {
+        Ok(replay) => commands.insert_resource(MapLoadPlayResource::create_replay(map.clone(), replay)),
+        Err(err) => warn!("Could not load the replay {0}: {1}", file, err),
+    }
+}
@@ -0,0 +1,186 @@
+#[cfg(feature = "client")]
+use bevy::ecs::system::Resource;
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 2.0;
+pub const SPEED_STEP: f32 = 0.1;
+const HARD_ROCK_HITBOX_SCALE: f32 = 0.75;
+// Notes fade out between these two times before being hit (ms)
+const HIDDEN_FADE_OUT: (f32, f32) = (300., 150.);
+const GHOST_FADE_OUT: (f32, f32) = (450., 300.);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Modifier {
+    NoFail,
+    HardRock,
+    Hidden,
+    Ghost,
+    Mirror,
+    Flip,
+    Autoplay
+}
+
+// In the order they are shown in the menu
+pub const MODIFIERS: [Modifier; 7] = [Modifier::NoFail, Modifier::HardRock, Modifier::Hidden, Modifier::Ghost, Modifier::Mirror, Modifier::Flip, Modifier::Autoplay];
+
+impl Modifier {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Modifier::NoFail => "No Fail",
+            Modifier::HardRock => "Hard Rock",
+            Modifier::Hidden => "Hidden",
+            Modifier::Ghost => "Ghost",
+            Modifier::Mirror => "Mirror",
+            Modifier::Flip => "Flip",
+            Modifier::Autoplay => "Autoplay",
+        }
+    }
+
+    pub fn acronym(&self) -> &'static str {
+        match self {
+            Modifier::NoFail => "NF",
+            Modifier::HardRock => "HR",
+            Modifier::Hidden => "HD",
+            Modifier::Ghost => "GH",
+            Modifier::Mirror => "MR",
+            Modifier::Flip => "FL",
+            Modifier::Autoplay => "AT",
+        }
+    }
+
+    // Bit of the modifier in the replay flags, these can't change or older replays would be read wrong
+    pub fn flag(&self) -> u8 {
+        let bit = match self {
+            Modifier::NoFail => 0,
+            Modifier::HardRock => 1,
+            Modifier::Hidden => 2,
+            Modifier::Ghost => 3,
+            Modifier::Mirror => 4,
+            Modifier::Autoplay => 5,
+            Modifier::Flip => 6,
+        };
+        return 1 << bit;
+    }
+
+    pub fn score_multiplier(&self) -> f32 {
+        match self {
+            Modifier::NoFail => 0.5,
+            Modifier::HardRock => 1.12,
+            Modifier::Hidden => 1.06,
+            Modifier::Ghost => 1.1,
+            Modifier::Mirror => 1.,
+            Modifier::Flip => 1.,
+            Modifier::Autoplay => 1.,
+        }
+    }
+}
+
+// The modifiers chosen in the menu, a copy is kept in `PlayStateData` for the whole play.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "client", derive(Resource))]
+pub struct Modifiers {
+    pub no_fail: bool,
+    // Smaller hitboxes
+    pub hard_rock: bool,
+    // Notes fade out right before they have to be hit
+    pub hidden: bool,
+    // Like hidden, but the notes fade out much earlier
+    pub ghost: bool,
+    // Notes are flipped horizontally
+    pub mirror: bool,
+    // Notes are flipped vertically, with mirror they are flipped on both axes
+    pub flip: bool,
+    // The cursor plays by itself, the scores are not saved
+    pub autoplay: bool,
+    // Song and notes speed, from MIN_SPEED to MAX_SPEED
+    pub speed: f32,
+    // Time stretch the song instead of playing it faster/slower, so the pitch stays the same
+    pub pitch_correct: bool,
+}
+
+impl Default for Modifiers {
+    fn default() -> Self {
+        Modifiers {
+            no_fail: false,
+            hard_rock: false,
+            hidden: false,
+            ghost: false,
+            mirror: false,
+            flip: false,
+            autoplay: false,
+            speed: 1.,
+            pitch_correct: false,
+        }
+    }
+}
+
+impl Modifiers {
+    pub fn is_enabled(&self, modifier: Modifier) -> bool {
+        match modifier {
+            Modifier::NoFail => self.no_fail,
+            Modifier::HardRock => self.hard_rock,
+            Modifier::Hidden => self.hidden,
+            Modifier::Ghost => self.ghost,
+            Modifier::Mirror => self.mirror,
+            Modifier::Flip => self.flip,
+            Modifier::Autoplay => self.autoplay,
+        }
+    }
+
+    pub fn toggle(&mut self, modifier: Modifier) {
+        match modifier {
+            Modifier::NoFail => self.no_fail = !self.no_fail,
+            Modifier::HardRock => self.hard_rock = !self.hard_rock,
+            Modifier::Hidden => self.hidden = !self.hidden,
+            Modifier::Ghost => self.ghost = !self.ghost,
+            Modifier::Mirror => self.mirror = !self.mirror,
+            Modifier::Flip => self.flip = !self.flip,
+            Modifier::Autoplay => self.autoplay = !self.autoplay,
+        }
+    }
+
+    // Rounded to the step so that repeated changes don't drift
+    pub fn change_speed(&mut self, steps: i32) {
+        let speed = ((self.speed / SPEED_STEP).round() + steps as f32) * SPEED_STEP;
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    pub fn enabled(&self) -> Vec<Modifier> {
+        return MODIFIERS.iter().copied().filter(|modifier| self.is_enabled(*modifier)).collect();
+    }
+
+    pub fn acronyms(&self) -> Vec<String> {
+        return self.enabled().iter().map(|modifier| modifier.acronym().to_owned()).collect();
+    }
+
+    pub fn score_multiplier(&self) -> f32 {
+        return self.enabled().iter().map(|modifier| modifier.score_multiplier()).product();
+    }
+
+    pub fn hitbox_scale(&self) -> f32 {
+        return if self.hard_rock { HARD_ROCK_HITBOX_SCALE } else { 1. };
+    }
+
+    // Opacity multiplier of a note that has to be hit in `time_until_hit_ms`
+    pub fn note_alpha(&self, time_until_hit_ms: f32) -> f32 {
+        let mut alpha: f32 = 1.;
+        if self.hidden {
+            alpha = alpha.min(fade_out(time_until_hit_ms, HIDDEN_FADE_OUT));
+        }
+        if self.ghost {
+            alpha = alpha.min(fade_out(time_until_hit_ms, GHOST_FADE_OUT));
+        }
+        return alpha;
+    }
+
+    // Mirror flips the x of the notes and flip their y
+    pub fn apply_mirror(&self, x: f32, y: f32) -> (f32, f32) {
+        let x = if self.mirror { -x } else { x };
+        let y = if self.flip { -y } else { y };
+        return (x, y);
+    }
+}
+
+fn fade_out(time_until_hit_ms: f32, (start, end): (f32, f32)) -> f32 {
+    return ((time_until_hit_ms - end) / (start - end)).clamp(0., 1.);
+}
@@ -1,12 +1,34 @@
-use bevy::{asset::Assets, ecs::{component::Component, event::EventReader, query::{With, Without}, system::{Commands, Query, ResMut}}, input::mouse::MouseMotion, math::{primitives::Rectangle, Vec3}, render::mesh::Mesh, transform::components::Transform, utils::default};
+use bevy::{asset::Assets, ecs::{component::Component, event::EventReader, query::{With, Without}, system::{Commands, Query, Res, ResMut}}, input::{gamepad::{GamepadAxis, GamepadAxisType, Gamepads}, keyboard::KeyCode, mouse::MouseMotion, Axis, ButtonInput}, math::{primitives::Rectangle, Vec2, Vec3}, render::mesh::Mesh, time::Time, transform::components::Transform, utils::default, window::{PrimaryWindow, Window}};
 use bevy_mod_billboard::{BillboardMeshHandle, BillboardTextureBundle, BillboardTextureHandle};
 
-use crate::startup::GlobalAssets;
+use crate::{engine::{self, autoplay::AutoplayPath}, map::GRID_HALF_SIZE, settings::{CursorArea, CursorEdge, CursorMode, Settings}, startup::GlobalAssets};
 
-use super::{note::PlayNote, play_state::InPlay};
+use super::{note::{get_play_time_ms, PlayNote}, play_state::{InPlay, PlayStateData, CAMERA_DISTANCE}, replay_playback::{get_watch_time_ms, replay_position, ReplayPlayback}};
 
-#[derive(Component)]
-pub struct Cursor;
+// Mouse movement (pixels) for the cursor to move by one grid cell at a sensitivity of 1
+const CURSOR_PIXELS_PER_CELL: f32 = 150.;
+// How far past the edge the mouse can drift, in sizes of the cursor area
+const DRIFT_LIMIT: f32 = 2.;
+
+// Keys for each grid cell in keyboard mode, as (column, row) from the top left of the screen
+const CELL_KEYS: [(KeyCode, KeyCode, i32, i32); 9] = [
+    (KeyCode::Numpad7, KeyCode::KeyQ, -1, -1),
+    (KeyCode::Numpad8, KeyCode::KeyW, 0, -1),
+    (KeyCode::Numpad9, KeyCode::KeyE, 1, -1),
+    (KeyCode::Numpad4, KeyCode::KeyA, -1, 0),
+    (KeyCode::Numpad5, KeyCode::KeyS, 0, 0),
+    (KeyCode::Numpad6, KeyCode::KeyD, 1, 0),
+    (KeyCode::Numpad1, KeyCode::KeyZ, -1, 1),
+    (KeyCode::Numpad2, KeyCode::KeyX, 0, 1),
+    (KeyCode::Numpad3, KeyCode::KeyC, 1, 1),
+];
+
+#[derive(Component, Default)]
+pub struct Cursor {
+    // Where the mouse puts the cursor, this is only outside of the cursor area while drifting past its edge
+    position: Vec2,
+    autoplay: AutoplayPath,
+}
 
 #[derive(Component)]
 pub struct CursorTransformParallax {
@@ -24,25 +46,124 @@ pub fn init_cursor(
         texture: BillboardTextureHandle(globals.cursor.clone()),
         mesh: BillboardMeshHandle(meshes.add(Rectangle::new(0.35, 0.35))),
         ..default()
-    }, InPlay, Cursor));
+    }, InPlay, Cursor::default()));
 }
 
 pub fn on_update(
     mut motion_reader: EventReader<MouseMotion>, 
-    mut q_cursor: Query<&mut Transform, (With<Cursor>, Without<PlayNote>)>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    data: Res<PlayStateData>,
+    playback: Option<Res<ReplayPlayback>>,
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+    mut q_cursor: Query<(&mut Cursor, &mut Transform), Without<PlayNote>>,
     mut q_parallax: Query<(&mut Transform, &CursorTransformParallax), (With<CursorTransformParallax>, Without<Cursor>)>
 ) {
-    // Update cursor position
-    let mut cursor_pos = q_cursor.get_single_mut().unwrap();
-    for ev in motion_reader.read() {
-        cursor_pos.translation.x -= ev.delta.x / 150.0;
-        cursor_pos.translation.y -= ev.delta.y / 150.0;
-        cursor_pos.translation.x = cursor_pos.translation.x.clamp(-1.5, 1.5);
-        cursor_pos.translation.y = cursor_pos.translation.y.clamp(-1.5, 1.5);
+    let Ok(window) = q_windows.get_single() else {
+        return;
+    };
+    let area = cursor_area(&settings, window);
+
+    // Update cursor position, the camera looks down +z so the world x axis goes to the left of the screen
+    let (mut cursor, mut cursor_pos) = q_cursor.get_single_mut().unwrap();
+    if let Some(replay) = &data.watching {
+        // Not clamped, the replay may have been played with a bigger cursor area
+        motion_reader.clear();
+        let watch_time_ms = get_watch_time_ms(&data, playback.as_deref(), time.elapsed(), settings.start_finish_wait);
+        cursor.position = replay_position(&replay.frames, watch_time_ms);
+        cursor_pos.translation.x = cursor.position.x;
+        cursor_pos.translation.y = cursor.position.y;
+        update_parallax(&cursor_pos, &mut q_parallax);
+        return;
+    }
+    if data.modifiers.autoplay {
+        motion_reader.clear();
+        let time_ms = get_play_time_ms(&data, time.elapsed(), settings.start_finish_wait);
+        // The next note to hit, once it is on screen
+        let approach_rate = data.note_approach(&settings).rate;
+        let target = data.play.next_note()
+            .filter(|note| note.hit_ms - approach_rate < time_ms)
+            .map(|note| (engine::Vec2::new(note.x, note.y), note.hit_ms));
+        let current = engine::Vec2::new(cursor.position.x, cursor.position.y);
+        let position = cursor.autoplay.update(current, target, time_ms, settings.autoplay_curve);
+        cursor.position = Vec2::new(position.x, position.y);
+        cursor_pos.translation.x = cursor.position.x;
+        cursor_pos.translation.y = cursor.position.y;
+        update_parallax(&cursor_pos, &mut q_parallax);
+        return;
+    }
+    match settings.cursor_mode {
+        CursorMode::Relative => {
+            for ev in motion_reader.read() {
+                cursor.position -= ev.delta / CURSOR_PIXELS_PER_CELL * settings.sensitivity;
+            }
+            let stick = get_stick_movement(&settings, &gamepads, &axes);
+            cursor.position += Vec2::new(-stick.x, stick.y) * time.delta_seconds();
+        }
+        CursorMode::Absolute => {
+            motion_reader.clear();
+            if let Some(window_pos) = window.cursor_position() {
+                // Scaled by the window height on both axes to keep the aspect ratio
+                let window_size = Vec2::new(window.width(), window.height());
+                let from_center = (window_pos - window_size / 2.) / (window_size.y / 2.);
+                cursor.position = -from_center * area.y * settings.sensitivity;
+            }
+        }
+        CursorMode::Keyboard => {
+            motion_reader.clear();
+            for (numpad, letter, column, row) in CELL_KEYS {
+                if keys.just_pressed(numpad) || keys.just_pressed(letter) {
+                    cursor.position = Vec2::new(-column as f32, -row as f32);
+                }
+            }
+        }
     }
+    let limit = match settings.cursor_edge {
+        CursorEdge::Border => area,
+        CursorEdge::Drift => area * DRIFT_LIMIT,
+    };
+    cursor.position = cursor.position.clamp(-limit, limit);
+    cursor_pos.translation.x = cursor.position.x.clamp(-area.x, area.x);
+    cursor_pos.translation.y = cursor.position.y.clamp(-area.y, area.y);
+    update_parallax(&cursor_pos, &mut q_parallax);
+}
 
+fn update_parallax(cursor_pos: &Transform, q_parallax: &mut Query<(&mut Transform, &CursorTransformParallax), (With<CursorTransformParallax>, Without<Cursor>)>) {
     for (mut transform, parallax) in q_parallax.iter_mut() {
         transform.translation.x = -cursor_pos.translation.x / parallax.parallax_amount;
         transform.translation.y = -cursor_pos.translation.y / parallax.parallax_amount;
     }
 }
+
+// Movement from the left sticks of all controllers in grid cells per second, +y is up on the stick
+fn get_stick_movement(settings: &Settings, gamepads: &Gamepads, axes: &Axis<GamepadAxis>) -> Vec2 {
+    let mut movement = Vec2::ZERO;
+    for gamepad in gamepads.iter() {
+        let stick = Vec2::new(
+            axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX)).unwrap_or(0.),
+            axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY)).unwrap_or(0.)
+        );
+        let length = stick.length().min(1.);
+        if length <= settings.stick_deadzone {
+            continue;
+        }
+        // Rescale past the deadzone so movement starts from zero, then apply the curve
+        let amount = ((length - settings.stick_deadzone) / (1. - settings.stick_deadzone).max(0.01)).powf(settings.stick_acceleration);
+        movement += stick.normalize() * amount * settings.stick_speed * settings.sensitivity;
+    }
+    return movement;
+}
+
+// Half of the size of the area the cursor can move in
+fn cursor_area(settings: &Settings, window: &Window) -> Vec2 {
+    match settings.cursor_area {
+        CursorArea::Grid => Vec2::splat(GRID_HALF_SIZE),
+        CursorArea::Camera => {
+            let half_height = CAMERA_DISTANCE * (settings.fov / 2.).to_radians().tan();
+            Vec2::new(half_height * window.width() / window.height().max(1.), half_height)
+        }
+    }
+}
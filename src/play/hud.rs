@@ -2,18 +2,10 @@ use bevy::{asset::{Assets, Handle}, ecs::{component::Component, query::{With, Wi
 use bevy_mod_billboard::{BillboardLockAxis, BillboardMeshHandle, BillboardTextBundle, BillboardTextureBundle, BillboardTextureHandle};
 use num_format::{Locale, ToFormattedString};
 
-use crate::startup::GlobalAssets;
+use crate::{engine::{judgement::HitResult, scoring::{calc_play_grade, get_play_grade_name, PlayGrade}}, startup::GlobalAssets};
 
 use super::{cursor::CursorTransformParallax, play_state::{InPlay, PlayStateData}};
 
-pub enum PlayGrade {
-    SS,
-    A,
-    B,
-    C,
-    D
-}
-
 #[derive(Component)]
 pub struct LeftPlayGradeText;
 
@@ -26,6 +18,12 @@ pub struct RightPanelText;
 #[derive(Component)]
 pub struct PlayGrid;
 
+#[derive(Component)]
+pub struct HealthBar;
+
+const HEALTH_BAR_WIDTH: f32 = 3.;
+const HEALTH_BAR_Y: f32 = -1.65;
+
 pub fn init_hud(
     mut data: ResMut<PlayStateData>, 
     mut meshes: ResMut<Assets<Mesh>>,
@@ -61,13 +59,52 @@ pub fn init_hud(
         ..default()
     }, InPlay));
 
+    // Health bar under the grid, there is no health to show with no fail
+    if !data.modifiers.no_fail {
+        commands.spawn((BillboardTextureBundle {
+            transform: Transform::from_translation(Vec3::new(0., HEALTH_BAR_Y, 0.01)),
+            texture: BillboardTextureHandle(images.add(Image::new_fill(Extent3d {
+                width: 1_u32,
+                height: 1_u32,
+                depth_or_array_layers: 1,
+            }, TextureDimension::D2, &[1, 1, 1, 100], TextureFormat::Rgba8Unorm, RenderAssetUsages::all()))),
+            mesh: BillboardMeshHandle(meshes.add(Rectangle::new(HEALTH_BAR_WIDTH, 0.06))),
+            ..default()
+        }, InPlay));
+        commands.spawn((BillboardTextureBundle {
+            transform: Transform::from_translation(Vec3::new(0., HEALTH_BAR_Y, 0.)),
+            texture: BillboardTextureHandle(images.add(Image::new_fill(Extent3d {
+                width: 1_u32,
+                height: 1_u32,
+                depth_or_array_layers: 1,
+            }, TextureDimension::D2, &[255, 255, 255, 220], TextureFormat::Rgba8Unorm, RenderAssetUsages::all()))),
+            mesh: BillboardMeshHandle(meshes.add(Rectangle::new(HEALTH_BAR_WIDTH, 0.06))),
+            ..default()
+        }, InPlay, HealthBar));
+    }
+
+    // Enabled modifiers, under the grid
+    if !data.modifiers.enabled().is_empty() {
+        commands.spawn((BillboardTextBundle {
+            transform: Transform::from_translation(Vec3::new(0., -1.85, 0.))
+                .with_scale(Vec3::splat(0.005)),
+            text: Text::from_section(data.modifiers.acronyms().join(" "), TextStyle {
+                font_size: 24.0,
+                color: Color::rgb(0.8, 0.8, 0.8),
+                font: globals.main_font.clone(),
+            }).with_justify(bevy::text::JustifyText::Center),
+            ..default()
+        }, InPlay));
+    }
+
     // Left panel text
     commands.spawn((BillboardTextBundle {
         transform: Transform::from_translation(Vec3::new(2.5, 0., -0.3))
             .with_scale(Vec3::splat(0.0025)).looking_at(Vec3::new(0., 0., -10.), Vec3::Y),
         text: build_left_panel_text(
-            data.current_combo, 
-            data.get_accuracy(), 
+            data.play.current_combo, 
+            data.play.accuracy(), 
+            &data,
             globals.main_font.clone()
         ),
         ..default()
@@ -140,7 +177,7 @@ pub fn init_hud(
     commands.spawn((BillboardTextBundle {
         transform: Transform::from_translation(Vec3::new(-2.5, 0., -0.3))
             .with_scale(Vec3::splat(0.0025)).looking_at(Vec3::new(0., 0., -10.), Vec3::Y),
-        text: build_right_panel_text(0, data.misses, data.objects_hit, data.note_data.0.len() as i128, globals.main_font.clone()),
+        text: build_right_panel_text(0, data.play.misses, data.play.hits, data.note_data.0.len() as i128, globals.main_font.clone()),
         ..default()
     },
     BillboardLockAxis {
@@ -151,6 +188,7 @@ pub fn init_hud(
 
 pub fn on_update(
     mut data: ResMut<PlayStateData>, 
+    mut q_health_bar: Query<&mut Transform, With<HealthBar>>,
     mut q_left_panel_text: Query<&mut Text, With<LeftPanelText>>,
     mut q_right_panel_text: Query<&mut Text, (Without<LeftPanelText>, With<RightPanelText>)>,
     mut q_play_grade_text: Query<&mut Text, (With<LeftPlayGradeText>, Without<RightPanelText>, Without<LeftPanelText>)>,
@@ -159,22 +197,31 @@ pub fn on_update(
     // Update the left panel info
     let mut left_panel_text = q_left_panel_text.get_single_mut().unwrap();
     *left_panel_text = build_left_panel_text(
-        data.current_combo, 
-        data.get_accuracy(),
+        data.play.current_combo, 
+        data.play.accuracy(),
+        &data,
         globals.main_font.clone()
     );
 
     // Update the right panel info
     let mut right_panel_text = q_right_panel_text.get_single_mut().unwrap();
-    *right_panel_text = build_right_panel_text(calc_score(data.objects_hit, data.max_combo, data.get_accuracy()), data.misses, data.objects_hit, data.note_data.0.len() as i128, globals.main_font.clone());
+    *right_panel_text = build_right_panel_text(data.play.score(), data.play.misses, data.play.hits, data.note_data.0.len() as i128, globals.main_font.clone());
 
     // Update play grade
     let mut play_grade_text = q_play_grade_text.get_single_mut().unwrap();
-    *play_grade_text = build_play_grade_text(calc_play_grade(data.get_accuracy()), globals.main_font.clone());
+    *play_grade_text = build_play_grade_text(data.play.grade(), globals.main_font.clone());
+
+    // Shrink the health bar towards the left of the screen (+x, the camera looks down +z)
+    if let Ok(mut transform) = q_health_bar.get_single_mut() {
+        let health = data.play.health.health;
+        transform.scale.x = health.max(0.001);
+        transform.translation.x = HEALTH_BAR_WIDTH / 2. * (1. - health);
+    }
 }
 
-fn build_left_panel_text(combo: i128, accuracy: f32, font: Handle<Font>) -> Text {
-    Text::from_sections([
+// The judgement counts are shown under the accuracy, in the color of their tier
+fn build_left_panel_text(combo: i128, accuracy: f32, data: &PlayStateData, font: Handle<Font>) -> Text {
+    let mut text = Text::from_sections([
         TextSection {
             value: "\nCOMBO".to_string(),
             style: TextStyle {
@@ -199,7 +246,18 @@ fn build_left_panel_text(combo: i128, accuracy: f32, font: Handle<Font>) -> Text
                 font: font.clone(),
             }
         },
-    ]).with_justify(bevy::text::JustifyText::Center)
+    ]).with_justify(bevy::text::JustifyText::Center);
+    for (i, result) in [HitResult::Perfect, HitResult::Great, HitResult::Good].iter().enumerate() {
+        text.sections.push(TextSection {
+            value: (if i == 0 { "" } else { " / " }).to_owned() + &data.play.count_judgements(*result).to_string(),
+            style: TextStyle {
+                font_size: 64.0,
+                color: get_hit_result_color(*result),
+                font: font.clone(),
+            }
+        });
+    }
+    return text;
 }
 
 fn build_right_panel_text(score: i128, misses: i128, hits: i128, max_hits: i128, font: Handle<Font>) -> Text {
@@ -247,43 +305,9 @@ fn build_right_panel_text(score: i128, misses: i128, hits: i128, max_hits: i128,
     ]).with_justify(bevy::text::JustifyText::Center)
 }
 
-fn calc_play_grade(accuracy: f32) -> PlayGrade {
-    if accuracy == 100. {
-        return PlayGrade::SS;
-    } else if accuracy > 93.5 {
-        return PlayGrade::A;
-    } else if accuracy > 87.5 {
-        return PlayGrade::B;
-    } else if accuracy > 78.5 {
-        return PlayGrade::C;
-    } else {
-        return PlayGrade::D;
-    }
-}
-
-fn calc_score(hits: i128, max_combo: i128, accuracy: f32) -> i128 {
-    return ((hits * 5 * max_combo) as f32 * (0.8 + accuracy / 500.)) as i128;
-}
-
 fn build_play_grade_text(grade: PlayGrade, font: Handle<Font>) -> Text {
     Text::from_section(
-        match grade {
-            PlayGrade::SS => {
-                "SS".to_string()
-            },
-            PlayGrade::A => {
-                "A".to_string()
-            },
-            PlayGrade::B => {
-                "B".to_string()
-            },
-            PlayGrade::C => {
-                "C".to_string()
-            },
-            PlayGrade::D => {
-                "D".to_string()
-            },
-        },
+        get_play_grade_name(grade),
         TextStyle {
             font_size: 146.0,
             color: get_play_grade_color(grade),
@@ -292,7 +316,16 @@ fn build_play_grade_text(grade: PlayGrade, font: Handle<Font>) -> Text {
     )
 }
 
-fn get_play_grade_color(grade: PlayGrade) -> Color {
+pub (crate) fn get_hit_result_color(result: HitResult) -> Color {
+    match result {
+        HitResult::Perfect => Color::hex("#67d6ff").unwrap(),
+        HitResult::Great => Color::hex("#6aff7e").unwrap(),
+        HitResult::Good => Color::hex("#ffd166").unwrap(),
+        HitResult::Miss => Color::hex("#c41d31").unwrap(),
+    }
+}
+
+pub (crate) fn get_play_grade_color(grade: PlayGrade) -> Color {
     match grade {
         PlayGrade::SS => {
             Color::hex("#e329d7").unwrap()
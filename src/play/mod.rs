@@ -1,5 +1,8 @@
 pub mod play_state;
-mod note;
-mod hud;
+pub (crate) mod note;
+pub (crate) mod hud;
 mod cursor;
-mod sound;
\ No newline at end of file
+mod sound;
+mod pause;
+mod time_stretch;
+pub (crate) mod replay_playback;
\ No newline at end of file
@@ -1,32 +1,24 @@
 use bevy::{asset::Handle, ecs::{component::Component, system::Resource}, pbr::StandardMaterial, render::view::VisibilityBundle};
 
-use bevy::{asset::Assets, ecs::{entity::Entity, query::{With, Without}, schedule::NextState, system::{Commands, Query, Res, ResMut}}, log::info, math::Vec3, pbr::{AlphaMode, PbrBundle}, render::{color::Color, view::Visibility}, time::Time, transform::components::Transform, utils::default};
+use std::time::Duration;
+
+use bevy::{asset::Assets, ecs::{entity::Entity, query::{With, Without}, schedule::NextState, system::{Commands, Query, Res, ResMut}}, log::{error, info}, math::Vec3, pbr::{AlphaMode, PbrBundle}, render::{color::Color, view::Visibility}, time::Time, transform::components::Transform, utils::default};
 use bevy_kira_audio::prelude::*;
 
-use crate::{map::{Note, NoteData}, startup::GlobalAssets, state::GameState};
+use crate::{engine::{self, judgement::NOTE_LATE_HIT_WINDOW, PlaySimulation}, map::NoteData, replay::ReplayFrame, settings::Settings, startup::GlobalAssets, state::GameState};
 
 use super::{cursor::Cursor, play_state::{InPlay, PlayStateData, PlayStatePlugin}};
 
-const APPROACH_RATE: i128 = 500;
-const APPROACH_DIST: f32 = 25.0;
-const NOTE_FADE_IN: i128 = 400;
-const WAIT_TIME_START_FINISH: i128 = 500;
 const GAME_RESYNC_THRESHOLD: i128 = 50;// How many milliseconds the audio has to be off of the game to trigger a resync
-const NOTE_EARLY_HIT_WINDOW: i128 = 0;
-const CURSOR_HITBOX: f32 = 0.2625/2.;
+const NOTE_MESH_SCALE: f32 = 0.45;// Scale of the note mesh for a note of size 1 (one grid cell)
 
+// A note on screen, it is judged by the `PlaySimulation` of the play
 #[derive(Component)]
 pub (crate) struct PlayNote {
-    pub x: f32,
-    pub y: f32,
+    // Index of the note in the simulation
+    pub index: usize,
     pub hit_ms: i128,
-    pub hit_result: Option<HitResult>,
-    pub note_material: Handle<StandardMaterial>
-}
-
-#[derive(PartialEq)]
-pub enum HitResult {
-    Hit, Miss
+    pub note_material: Handle<StandardMaterial>,
 }
 
 #[derive(Default, Resource)]
@@ -53,87 +45,58 @@ impl NotePaletteCycler {
     }
 }
 
-// Tracks which notes should be added next
-#[derive(Default)]
-pub struct MapNoteTracker {
-    data: NoteData,
-    last_update_time: i128
-}
-
-impl MapNoteTracker {
-    pub fn new(note_data: NoteData, play_speed: f32) -> Self {
-        let mut t = Self {
-            data: note_data.clone(),
-            last_update_time: 0
-        };
-        for note in &mut t.data.0 {
-            note.hit_ms = (note.hit_ms as f32 / play_speed as f32) as i128;
-        }
-
-        return t;
-    }
-
-    pub fn update_get_next(&mut self, time_ms: i128, approach_time_ms: i128) -> Option<Vec<Note>> {
-        if self.data.0.len() == 0 {
-            return None;
-        }
-        let mut notes: Vec<Note> = vec![];
-        for note in &mut self.data.0 {
-            if note.hit_ms - approach_time_ms < time_ms {
-                notes.push(note.clone());
-                continue;
-            }
-        }
-        /*if notes.len() == 1 && self.data.0.len() == 1 && time_ms < notes.get(0).unwrap().hit_ms + approach_time_ms{
-            return Some(notes);
-        }*/
-        self.data.0.retain(|x| 
-            !notes.contains(x)
-        );    
-        self.last_update_time = time_ms;
-        return Some(notes);
-    }
-
-    pub fn has_more_notes(&mut self) -> bool {
-        return !self.data.0.len() == 0;
-    }
+// Time since the start of the play (ms), on the same clock as the note hit times.
+// Negative while waiting for a first note that comes too early.
+pub fn get_play_time_ms(data: &PlayStateData, now: Duration, start_finish_wait: i128) -> i128 {
+    let elapsed_ms = now.as_millis() as i128 - data.start_time.as_millis() as i128 + data.time_offset_ms;
+    return engine::get_play_time_ms(elapsed_ms, data.play.first_note_ms(), start_finish_wait);
 }
 
 pub fn init_note_manager(mut data: ResMut<PlayStateData>,
     note_datas: ResMut<Assets<NoteData>>, 
-    globals: ResMut<GlobalAssets>,
+    settings: Res<Settings>,
+    mut state: ResMut<NextState<GameState>>,
     mut commands: Commands) {
-    data.note_data = note_datas.get(&data.map.notes).expect("Note data not found!").clone();
-    data.note_tracker = MapNoteTracker::new(data.note_data.clone(), data.play_speed);
-
-    let note_materials = NotePaletteCycler::new(globals.note_palette.clone());
+    let Some(notes) = note_datas.get(&data.map.notes) else {
+        error!("The notes of {0} are not loaded, going back to the menu", data.map.title);
+        state.set(GameState::Menu);
+        return;
+    };
+    data.note_data = NoteData(engine::get_play_notes(&notes.0, data.map.offset_ms, &data.modifiers));
+    // A watched replay is judged like it was when it was played
+    let windows = match &data.watching {
+        Some(replay) => replay.windows,
+        None => settings.judgement_windows(),
+    };
+    data.play = PlaySimulation::new(&data.note_data.0, &data.modifiers, windows);
+    data.note_tracker = data.play.note_tracker();
+
+    let note_materials = NotePaletteCycler::new(settings.note_palette_colors());
     commands.insert_resource(note_materials);
 }
 
 pub fn on_update(        
     mut data: ResMut<PlayStateData>, 
     time: ResMut<Time>, 
-    mut note_query: Query<(Entity, &mut Transform, &mut PlayNote, &mut Visibility), With<PlayNote>>, 
+    settings: Res<Settings>,
+    mut note_query: Query<(Entity, &mut Transform, &PlayNote, &mut Visibility), With<PlayNote>>, 
     globals: ResMut<GlobalAssets>,
     audio: Res<Audio>,
     mut note_palette: ResMut<NotePaletteCycler>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut audio_instances: ResMut<Assets<AudioInstance>>,
-    mut q_cursor: Query<&mut Transform, (With<Cursor>, Without<PlayNote>)>,
+    note_datas: Res<Assets<NoteData>>,
+    q_cursor: Query<&Transform, (With<Cursor>, Without<PlayNote>)>,
     mut state: ResMut<NextState<GameState>>,
     mut commands: Commands) {
+    // Going back to the menu, the play couldn't start
+    if !note_datas.contains(&data.map.notes) {
+        return;
+    }
     let current_time = time.elapsed();
     //current_time = PlayStatePlugin::duration_add_signed(current_time, data.resync_offset_ms);
-    let mut current_time_ms = current_time.as_millis() as i128 - data.start_time.as_millis() as i128;
-
-    let first_note_time = data.note_data.0[0].hit_ms;
-    let mut is_waiting_to_start = false;
-    // If the first note of the map is pops up too fast
-    // we will wait a little time so that it can pop up.
-    if first_note_time <= WAIT_TIME_START_FINISH {
-        current_time_ms -= WAIT_TIME_START_FINISH;
-        is_waiting_to_start = current_time_ms < 0;
-    }
+    let current_time_ms = get_play_time_ms(&data, current_time, settings.start_finish_wait);
+    let is_waiting_to_start = current_time_ms < 0;
 
     // Sync audio and game if required
     if let Some(instance) = audio_instances.get_mut(&data.song) {
@@ -143,10 +106,11 @@ pub fn on_update(
                     instance.pause(AudioTween::default());
                     return;
                 }
-                let audio_offset = ((position / data.play_speed as f64) * 1000.0) as i128 - current_time_ms;
+                // The song instance plays at the audio rate compared to the game clock
+                let audio_offset = ((position / data.audio_rate as f64) * 1000.0) as i128 - current_time_ms;
                 if audio_offset.abs() >= GAME_RESYNC_THRESHOLD {
                     info!("Resyncing audio by {0}ms", audio_offset);
-                    instance.seek_to((current_time_ms as f64 / 1000.) * data.play_speed as f64);
+                    instance.seek_to((current_time_ms as f64 / 1000.) * data.audio_rate as f64);
                     instance.resume(AudioTween::default());
                 }
             }
@@ -158,62 +122,74 @@ pub fn on_update(
         }
     }
 
-    let cursor_pos = q_cursor.get_single_mut().unwrap();
-
-    // Update/remove the current notes
-    for (entity, mut transform, mut note, mut visibility) in &mut note_query {
-        // Testing...
-        if current_time_ms > note.hit_ms + 200 {
-            commands.entity(entity).despawn();
-            if note.hit_result.is_none() {
-                note.hit_result = Some(HitResult::Miss);
-                data.current_combo = 0;
-                data.misses += 1;
+    // A watched replay is judged on its recorded frames, so the results are the same as in the play
+    let cursor_pos = q_cursor.get_single().unwrap();
+    let frames: Vec<ReplayFrame> = match &data.watching {
+        Some(replay) => {
+            let judged_ms = data.play.frames.last().map_or(i128::MIN, |frame| frame.time_ms as i128);
+            let next = replay.frames.partition_point(|frame| frame.time_ms as i128 <= judged_ms);
+            replay.frames[next..].iter().take_while(|frame| frame.time_ms as i128 <= current_time_ms).copied().collect()
+        }
+        None => vec![ReplayFrame {
+            time_ms: current_time_ms as i32,
+            x: cursor_pos.translation.x,
+            y: cursor_pos.translation.y,
+        }],
+    };
+    let mut hit_notes: Vec<usize> = vec![];
+    for frame in frames {
+        for judged in data.play.update(frame.time_ms as i128, frame.x, frame.y) {
+            if judged.judgement.result.is_hit() {
+                hit_notes.push(judged.index);
+                audio.play(globals.hit_sound.clone()).with_volume(settings.hit_volume());
             }
         }
+    }
 
-        if current_time_ms > note.hit_ms - NOTE_EARLY_HIT_WINDOW && note.hit_result.is_none() {
-            // TODO: get the hit result, update the component, then store the result somewhere else
-            let did_hit = did_cursor_hit(&note, &cursor_pos);
-            if did_hit {
-                note.hit_result = Some(HitResult::Hit);
-                data.current_combo += 1;
-                data.objects_hit += 1;
-                if data.current_combo > data.max_combo {
-                    data.max_combo = data.current_combo;
-                }
-                *visibility = Visibility::Hidden;
-                audio.play(globals.hit_sound.clone()).with_volume(1.);
-            }
+    // Update/remove the current notes
+    let approach = data.note_approach(&settings);
+    for (entity, mut transform, note, mut visibility) in &mut note_query {
+        if hit_notes.contains(&note.index) {
+            *visibility = Visibility::Hidden;
         }
-        let z_ratio: f32 = (note.hit_ms - current_time_ms) as f32 / APPROACH_RATE as f32; 
-        let z: f32 = (z_ratio * APPROACH_DIST) as f32;
+        if current_time_ms > note.hit_ms + NOTE_LATE_HIT_WINDOW {
+            commands.entity(entity).despawn();
+        }
+        let z_ratio: f32 = (note.hit_ms - current_time_ms) as f32 / approach.rate as f32; 
+        let z: f32 = (z_ratio * approach.distance) as f32;
         transform.translation.z = z;
-        let alpha: f32 = if current_time_ms > note.hit_ms - APPROACH_RATE + NOTE_FADE_IN {
+        let fade_in: f32 = if current_time_ms > note.hit_ms - approach.rate + approach.fade_in {
             1.
         } else {
-            (current_time_ms - (note.hit_ms - APPROACH_RATE) as i128) as f32 / NOTE_FADE_IN as f32
+            (current_time_ms - (note.hit_ms - approach.rate) as i128) as f32 / approach.fade_in as f32
         };
+        // Hidden and ghost fade the notes back out as they get closer
+        let alpha = fade_in * data.modifiers.note_alpha((note.hit_ms - current_time_ms) as f32);
         let mat = materials.get_mut(&note.note_material);
         if mat.is_some() {
             mat.unwrap().base_color.set_a(alpha * 1000.);
         }
     }
 
+    if data.play.failed_at_ms.is_some() {
+        state.set(GameState::Results);
+        return;
+    }
+
     // Check if the map has ended
-    if !data.note_tracker.has_more_notes() && current_time_ms > (data.note_data.0[data.note_data.0.len() - 1].hit_ms as f32 / data.play_speed) as i128 + WAIT_TIME_START_FINISH {
-        state.set(GameState::Menu);
+    if !data.note_tracker.has_more_notes() && data.play.is_finished(current_time_ms, settings.start_finish_wait) {
+        state.set(GameState::Results);
         return;
     }
 
     // Add the notes that have just come into the approach rate field
-    let new_notes = data.note_tracker.update_get_next(current_time_ms, APPROACH_RATE);
+    let new_notes = data.note_tracker.update_get_next(current_time_ms, approach.rate);
     if new_notes.is_none() {
         return;
     }
-    for note in new_notes.unwrap() {
-        let z_ratio: f32 = (note.hit_ms - current_time_ms) as f32 / APPROACH_RATE as f32; 
-        let z: f32 = (z_ratio * APPROACH_DIST) as f32;
+    for (index, note) in new_notes.unwrap() {
+        let z_ratio: f32 = (note.hit_ms - current_time_ms) as f32 / approach.rate as f32; 
+        let z: f32 = (z_ratio * approach.distance) as f32;
         let note_color = note_palette.get_next().into();
         // Too many materials / inefficient ?
         let mat = materials.add(StandardMaterial {
@@ -226,29 +202,17 @@ pub fn on_update(
         });
         commands.spawn((
             PlayNote {
-                x: note.x,
-                y: note.y,
+                index,
                 hit_ms: note.hit_ms,
-                hit_result: None,
                 note_material: mat.clone(),
             },
             PbrBundle {
                 mesh: globals.note_mesh.clone(),
-                transform: Transform::from_xyz(note.x, note.y, z).with_scale(Vec3::new(0.45, 0.45, 0.45)),
+                transform: Transform::from_xyz(note.x, note.y, z).with_scale(Vec3::splat(NOTE_MESH_SCALE * note.size)),
                 material: mat.clone(),
                 ..default()
             },
             InPlay
         ));
     }
-}
-
-fn did_cursor_hit(note: &PlayNote, cursor_pos: &Transform) -> bool {
-    let left = note.x - 0.5 - CURSOR_HITBOX;
-    let top = note.y - 0.5 - CURSOR_HITBOX;
-    let right = note.x + 0.5 + CURSOR_HITBOX;
-    let bottom = note.y + 0.5 + CURSOR_HITBOX;
-    let x = cursor_pos.translation.x;
-    let y = cursor_pos.translation.y;
-    return left < x && x < right && top < y && y < bottom;
 }
\ No newline at end of file
@@ -0,0 +1,266 @@
+use std::time::Duration;
+
+use bevy::{asset::Assets, ecs::{component::Component, query::{Changed, With, Without}, schedule::NextState, system::{Commands, Query, Res, ResMut, Resource}}, hierarchy::BuildChildren, render::{color::Color, view::Visibility}, text::{Text, TextStyle}, time::Time, ui::{node_bundles::{ButtonBundle, NodeBundle, TextBundle}, widget::Button, AlignItems, BackgroundColor, FlexDirection, Interaction, JustifyContent, PositionType, Style, UiRect, Val}, utils::default};
+use bevy_kira_audio::prelude::*;
+
+use crate::{input::{MenuAction, MenuInput}, state::GameState};
+
+use super::play_state::{InPlay, MapLoadPlayResource, PlayStateData};
+
+const RESUME_COUNTDOWN: Duration = Duration::from_secs(3);
+
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const BUTTON_SELECTED_COLOR: Color = Color::rgb(0.5, 0.05, 0.7);
+
+// The game clock is frozen from the moment the game is paused until the resume countdown is over,
+// the time spent frozen is then added to `PlayStateData::start_time` so the notes continue where they stopped.
+#[derive(Resource, Default)]
+pub struct PlayPause {
+    paused_at: Option<Duration>,
+    resume_at: Option<Duration>,
+    selected: usize,
+}
+
+impl PlayPause {
+    pub fn is_frozen(&self) -> bool {
+        return self.paused_at.is_some();
+    }
+
+    // Paused with the pause menu open, not counting down
+    pub fn is_paused(&self) -> bool {
+        return self.paused_at.is_some() && self.resume_at.is_none();
+    }
+
+    fn pause(&mut self, now: Duration) {
+        self.paused_at = Some(now);
+        self.resume_at = None;
+        self.selected = 0;
+    }
+
+    fn start_resume(&mut self, now: Duration) {
+        self.resume_at = Some(now + RESUME_COUNTDOWN);
+    }
+}
+
+#[derive(Component, Clone, Copy, PartialEq)]
+pub enum PauseButton {
+    Resume,
+    Restart,
+    Quit
+}
+
+const PAUSE_BUTTONS: [PauseButton; 3] = [PauseButton::Resume, PauseButton::Restart, PauseButton::Quit];
+
+#[derive(Component)]
+pub struct PauseOverlay;
+
+#[derive(Component)]
+pub struct PauseCountdownText;
+
+pub fn is_not_frozen(pause: Option<Res<PlayPause>>) -> bool {
+    return pause.map_or(true, |pause| !pause.is_frozen());
+}
+
+pub fn init_pause(mut commands: Commands) {
+    commands.insert_resource(PlayPause::default());
+
+    commands.spawn((NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            position_type: PositionType::Absolute,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.7)),
+        visibility: Visibility::Hidden,
+        ..default()
+    }, PauseOverlay, InPlay)).with_children(|builder| {
+        builder.spawn(TextBundle {
+            style: Style {
+                margin: UiRect::px(10., 10., 10., 30.),
+                ..default()
+            },
+            text: Text::from_section("Paused", TextStyle {
+                font_size: 40.,
+                color: Color::rgb(1., 1., 1.),
+                ..default()
+            }),
+            ..default()
+        });
+        for button in PAUSE_BUTTONS {
+            builder.spawn((ButtonBundle {
+                style: Style {
+                    width: Val::Px(300.),
+                    height: Val::Px(60.),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    margin: UiRect::px(10., 10., 10., 10.),
+                    ..default()
+                },
+                background_color: BackgroundColor(BUTTON_COLOR),
+                ..default()
+            }, button)).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    match button {
+                        PauseButton::Resume => "Resume",
+                        PauseButton::Restart => "Restart",
+                        PauseButton::Quit => "Quit to menu",
+                    },
+                    TextStyle {
+                        font_size: 26.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..default()
+                    },
+                ));
+            });
+        }
+    });
+
+    commands.spawn((NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            position_type: PositionType::Absolute,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        ..default()
+    }, InPlay)).with_children(|builder| {
+        builder.spawn((TextBundle {
+            text: Text::from_section("", TextStyle {
+                font_size: 120.,
+                color: Color::rgb(1., 1., 1.),
+                ..default()
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        }, PauseCountdownText));
+    });
+}
+
+pub fn on_pause_keys(
+    input: MenuInput,
+    time: Res<Time>,
+    mut pause: ResMut<PlayPause>,
+    data: Res<PlayStateData>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut commands: Commands,
+    mut state: ResMut<NextState<GameState>>
+) {
+    if input.just_pressed(MenuAction::Pause) {
+        if pause.is_paused() {
+            pause.start_resume(time.elapsed());
+        } else {
+            // Pausing again during the countdown goes back to the pause menu
+            let paused_at = pause.paused_at.unwrap_or(time.elapsed());
+            pause.pause(paused_at);
+            if let Some(instance) = audio_instances.get_mut(&data.song) {
+                instance.pause(AudioTween::default());
+            }
+        }
+        return;
+    }
+
+    if !pause.is_paused() {
+        return;
+    }
+    if input.just_pressed(MenuAction::Down) {
+        pause.selected = (pause.selected + 1).min(PAUSE_BUTTONS.len() - 1);
+    }
+    if input.just_pressed(MenuAction::Up) {
+        pause.selected = pause.selected.saturating_sub(1);
+    }
+    if input.just_pressed(MenuAction::Confirm) {
+        let button = PAUSE_BUTTONS[pause.selected];
+        on_pause_action(button, &mut pause, &time, &data, &mut commands, &mut state);
+    }
+}
+
+pub fn on_pause_button(
+    interaction_query: Query<(&Interaction, &PauseButton), (Changed<Interaction>, With<Button>)>,
+    time: Res<Time>,
+    mut pause: ResMut<PlayPause>,
+    data: Res<PlayStateData>,
+    mut commands: Commands,
+    mut state: ResMut<NextState<GameState>>
+) {
+    if !pause.is_paused() {
+        return;
+    }
+    for (interaction, button) in &interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                on_pause_action(*button, &mut pause, &time, &data, &mut commands, &mut state);
+            }
+            Interaction::Hovered => {
+                pause.selected = PAUSE_BUTTONS.iter().position(|b| b == button).unwrap_or(0);
+            }
+            Interaction::None => {
+
+            }
+        }
+    }
+}
+
+fn on_pause_action(
+    button: PauseButton,
+    pause: &mut PlayPause,
+    time: &Time,
+    data: &PlayStateData,
+    commands: &mut Commands,
+    state: &mut NextState<GameState>
+) {
+    match button {
+        PauseButton::Resume => pause.start_resume(time.elapsed()),
+        PauseButton::Restart => commands.insert_resource(match &data.watching {
+            Some(replay) => MapLoadPlayResource::create_replay(data.map.clone(), replay.clone()),
+            None => MapLoadPlayResource::create_loaded(data.map.clone(), data.modifiers.clone()),
+        }),
+        PauseButton::Quit => state.set(GameState::Menu),
+    }
+}
+
+pub fn update_pause(
+    time: Res<Time>,
+    mut pause: ResMut<PlayPause>,
+    mut data: ResMut<PlayStateData>,
+    mut q_overlay: Query<&mut Visibility, With<PauseOverlay>>,
+    mut q_countdown: Query<(&mut Text, &mut Visibility), (With<PauseCountdownText>, Without<PauseOverlay>)>,
+    mut q_buttons: Query<(&PauseButton, &mut BackgroundColor)>
+) {
+    let paused = pause.is_paused();
+    for mut visibility in &mut q_overlay {
+        *visibility = if paused { Visibility::Inherited } else { Visibility::Hidden };
+    }
+    for (button, mut background) in &mut q_buttons {
+        *background = if *button == PAUSE_BUTTONS[pause.selected] {
+            BackgroundColor(BUTTON_SELECTED_COLOR)
+        } else {
+            BackgroundColor(BUTTON_COLOR)
+        };
+    }
+
+    let Ok((mut countdown_text, mut countdown_visibility)) = q_countdown.get_single_mut() else {
+        return;
+    };
+    let (Some(paused_at), Some(resume_at)) = (pause.paused_at, pause.resume_at) else {
+        *countdown_visibility = Visibility::Hidden;
+        return;
+    };
+    let now = time.elapsed();
+    if now >= resume_at {
+        // Shift the start of the play by the time spent frozen
+        data.start_time += now - paused_at;
+        pause.paused_at = None;
+        pause.resume_at = None;
+        *countdown_visibility = Visibility::Hidden;
+        return;
+    }
+    let remaining = (resume_at - now).as_secs_f32().ceil() as u32;
+    countdown_text.sections[0].value = remaining.to_string();
+    *countdown_visibility = Visibility::Inherited;
+}
@@ -1,34 +1,57 @@
-use std::{ops::{Add, Sub}, time::Duration};
+use std::{f32::consts::PI, ops::{Add, Sub}, time::Duration};
 
-use bevy::{app::{Plugin, Update}, asset::{AssetServer, Assets, Handle, LoadState}, core_pipeline::core_3d::Camera3dBundle, ecs::{change_detection::DetectChanges, component::Component, entity::Entity, query::With, schedule::{common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, OnExit}, system::{Commands, Query, Res, ResMut, Resource}}, hierarchy::DespawnRecursiveExt, math::{primitives::Cuboid, Vec3}, pbr::{AmbientLight, PbrBundle, StandardMaterial}, render::{camera::{PerspectiveProjection, Projection}, color::Color, mesh::Mesh}, transform::components::Transform, utils::default, window::{CursorGrabMode, PrimaryWindow, Window}};
+use bevy::{app::{Plugin, Update}, asset::{Assets, Handle, LoadState}, core_pipeline::core_3d::Camera3dBundle, ecs::{change_detection::DetectChanges, component::Component, entity::Entity, query::With, schedule::{common_conditions::{in_state, resource_exists}, IntoSystemConfigs, NextState, OnEnter, OnExit}, system::{Commands, Query, Res, ResMut, Resource}}, hierarchy::DespawnRecursiveExt, math::{primitives::{Cuboid, Rectangle}, Quat, Vec2, Vec3}, pbr::{AmbientLight, PbrBundle, StandardMaterial}, render::{camera::{PerspectiveProjection, Projection}, color::Color, mesh::Mesh}, transform::components::Transform, utils::default, window::{CursorGrabMode, PrimaryWindow, Window}};
 use bevy_kira_audio::prelude::*;
 
-use crate::{map::{Map, NoteData}, startup::GlobalAssets, state::GameState};
+use crate::{engine::{tracker::MapNoteTracker, PlaySimulation}, loading::LoadingTracker, map::{Map, NoteData}, modifiers::Modifiers, replay::Replay, settings::{CursorMode, Settings}, startup::GlobalAssets, state::GameState};
 
-use super::{cursor::{self, CursorTransformParallax}, hud, note::{self, MapNoteTracker}, sound};
+use super::{cursor::{self, CursorTransformParallax}, hud, note, pause::{self, PlayPause}, replay_playback, sound::{self, SongPreparation, StretchedSongs}};
+
+pub const CAMERA_DISTANCE: f32 = 4.;// Distance between the camera and the grid
+const BACKGROUND_DISTANCE: f32 = 40.;
+const BACKGROUND_SIZE: Vec2 = Vec2::new(112., 63.);// Fills a 70 degrees FOV at BACKGROUND_DISTANCE, scaled for other FOVs
+const BACKGROUND_FOV: f32 = 70.;
 
 #[derive(Resource, Default)]
 pub struct PlayStateData {
     pub map: Map,
+    pub audio: Handle<AudioSource>,
     pub song: Handle<AudioInstance>,
     pub note_data: NoteData,
     pub note_tracker: MapNoteTracker,
     pub start_time: Duration,
+    // Added to the play time, moved by seeking in a watched replay
+    pub time_offset_ms: i128,
     //pub last_update_time: Duration,
-    pub current_combo: i128,
-    pub objects_hit: i128,
-    pub misses: i128,
-    pub max_combo: i128,
     pub play_speed: f32,
+    // Playback rate of the song instance, the play speed unless the song was time stretched
+    pub audio_rate: f32,
+    pub modifiers: Modifiers,
+    // Judgements, score and health of the play, and the cursor frames saved as its replay
+    pub play: PlaySimulation,
+    // The replay being watched, the cursor then follows the replay instead of the mouse
+    pub watching: Option<Replay>,
+}
+
+// How the notes come in, the approach rate and fade in are in ms
+pub struct NoteApproach {
+    pub rate: i128,
+    pub distance: f32,
+    pub fade_in: i128,
 }
 
 impl PlayStateData {
-    // Normalized to 0 - 100
-    pub fn get_accuracy(&mut self) -> f32 {
-        if self.misses == 0 {
-            return 100.;
-        }
-        return (self.objects_hit as f32 / (self.objects_hit + self.misses) as f32) * 100.;
+    // A watched replay shows the notes like they were when it was played
+    pub fn note_approach(&self, settings: &Settings) -> NoteApproach {
+        let (rate, distance, fade_in) = match &self.watching {
+            Some(replay) => (replay.approach_rate, replay.approach_distance, replay.fade_in),
+            None => (settings.approach_rate, settings.approach_distance, settings.fade_in),
+        };
+        return NoteApproach {
+            rate,
+            distance,
+            fade_in: fade_in.clamp(1, rate.max(1)),
+        };
     }
 }
 
@@ -45,12 +68,25 @@ pub struct PlayCamera;
 #[derive(Resource)]
 pub struct MapLoadPlayResource {
     map: Map,
+    modifiers: Modifiers,
+    replay: Option<Replay>,
 }
 
 impl MapLoadPlayResource {
-    pub fn create_loaded(map: Map) -> MapLoadPlayResource {
+    pub fn create_loaded(map: Map, modifiers: Modifiers) -> MapLoadPlayResource {
         MapLoadPlayResource {
-            map
+            map,
+            modifiers,
+            replay: None
+        }
+    }
+
+    // Watches the replay on its map, with the modifiers it was played with
+    pub fn create_replay(map: Map, replay: Replay) -> MapLoadPlayResource {
+        MapLoadPlayResource {
+            map,
+            modifiers: replay.modifiers.clone(),
+            replay: Some(replay)
         }
     }
 }
@@ -68,10 +104,23 @@ fn poll_map_load_play(
 
     if added {
         let mut play_state_data = PlayStateData::default();
-        play_state_data.play_speed = 1.;
+        play_state_data.play_speed = map_load_play.modifiers.speed;
+        play_state_data.audio_rate = map_load_play.modifiers.speed;
         play_state_data.map = map_load_play.map.clone();
+        play_state_data.modifiers = map_load_play.modifiers.clone();
+        play_state_data.watching = map_load_play.replay.clone();
+
+        // The map is played once its notes, information and song are loaded
+        let mut tracker = LoadingTracker::new(format!("Loading {0}...", play_state_data.map.title), GameState::Play, Some(GameState::Menu))
+            .track("notes", &play_state_data.map.notes)
+            .wait_for(sound::SONG_JOB);
+        if let Some(metadata) = &play_state_data.map.metadata {
+            tracker.add("map information", metadata);
+        }
+        commands.insert_resource(SongPreparation::new(play_state_data.map.metadata.clone()));
+        commands.insert_resource(tracker);
         commands.insert_resource(play_state_data);
-        state.set(GameState::Play);
+        state.set(GameState::Loading);
         commands.remove_resource::<MapLoadPlayResource>();
     }
 }
@@ -79,15 +128,16 @@ fn poll_map_load_play(
 impl PlayStatePlugin {
     fn on_enter(
         mut data: ResMut<PlayStateData>,
+        settings: Res<Settings>,
         mut meshes: ResMut<Assets<Mesh>>,
         mut materials: ResMut<Assets<StandardMaterial>>,
         mut commands: Commands
     ) { 
         // Spawn camera
         commands.spawn((Camera3dBundle {
-            transform: Transform::from_xyz(0.0, 0.0, -4.0).looking_at(Vec3::ZERO, Vec3::Y),
+            transform: Transform::from_xyz(0.0, 0.0, -CAMERA_DISTANCE).looking_at(Vec3::ZERO, Vec3::Y),
             projection: Projection::Perspective(PerspectiveProjection {
-                fov: 70.0_f32.to_radians(),
+                fov: settings.fov.to_radians(),
                 aspect_ratio: 16./9.,
                 ..default()
             }),
@@ -112,6 +162,24 @@ impl PlayStatePlugin {
             }, InPlay
         ));
 
+        // Map background, dimmed behind the grid
+        if let Some(background) = &data.map.cover {
+            commands.spawn((
+                PbrBundle {
+                    mesh: meshes.add(Rectangle::from_size(BACKGROUND_SIZE * (settings.fov / 2.).to_radians().tan() / (BACKGROUND_FOV / 2.).to_radians().tan())),
+                    material: materials.add(StandardMaterial {
+                        base_color: Color::rgb(0.2, 0.2, 0.2),
+                        base_color_texture: Some(background.clone()),
+                        unlit: true,
+                        ..default()
+                    }),
+                    // Turned around to face the camera
+                    transform: Transform::from_xyz(0., 0., BACKGROUND_DISTANCE).with_rotation(Quat::from_rotation_y(PI)),
+                    ..default()
+                }, InPlay
+            ));
+        }
+
         // Ambient lighting
         commands.insert_resource(AmbientLight {
             color: Color::WHITE,
@@ -119,13 +187,33 @@ impl PlayStatePlugin {
         });
     }
 
-    fn update_window_cursor_state(mut q_windows: Query<&mut Window, With<PrimaryWindow>>) {
+    fn update_window_cursor_state(pause: Res<PlayPause>, settings: Res<Settings>, mut q_windows: Query<&mut Window, With<PrimaryWindow>>) {
         let mut window = q_windows.single_mut();
-        window.cursor.grab_mode = CursorGrabMode::Locked;
-        window.cursor.visible = false;
+        // The pause menu needs the mouse
+        if pause.is_paused() {
+            window.cursor.grab_mode = CursorGrabMode::None;
+            window.cursor.visible = true;
+        } else {
+            // The absolute cursor needs the position of the mouse on the window, which isn't updated while it is locked
+            window.cursor.grab_mode = match settings.cursor_mode {
+                CursorMode::Relative | CursorMode::Keyboard => CursorGrabMode::Locked,
+                CursorMode::Absolute => CursorGrabMode::Confined,
+            };
+            window.cursor.visible = false;
+        }
     }
 
-    fn on_exit(mut commands: Commands, mut q_windows: Query<&mut Window, With<PrimaryWindow>>, q_entities: Query<Entity, With<InPlay>>) {
+    fn on_exit(
+        mut commands: Commands,
+        data: Res<PlayStateData>,
+        mut audio_instances: ResMut<Assets<AudioInstance>>,
+        mut q_windows: Query<&mut Window, With<PrimaryWindow>>,
+        q_entities: Query<Entity, With<InPlay>>
+    ) {
+        // The song is still playing when quitting or restarting in the middle of the map
+        if let Some(instance) = audio_instances.get_mut(&data.song) {
+            instance.stop(AudioTween::default());
+        }
         let mut window = q_windows.single_mut();
         window.cursor.grab_mode = CursorGrabMode::None;
         window.cursor.visible = true;
@@ -133,6 +221,7 @@ impl PlayStatePlugin {
             commands.entity(ent).despawn_recursive();
         } 
         commands.remove_resource::<AmbientLight>();
+        commands.remove_resource::<PlayPause>();
     }
 
     pub fn duration_add_signed(a: Duration, add: i128) -> Duration {
@@ -151,22 +240,32 @@ impl Plugin for PlayStatePlugin {
             hud::init_hud,
             cursor::init_cursor,
             note::init_note_manager,
-            sound::init_sound
+            sound::init_sound,
+            pause::init_pause,
+            replay_playback::init_replay_playback.run_if(replay_playback::is_watching)
         ));
-        app.add_systems(OnExit(GameState::Play), PlayStatePlugin::on_exit);
+        app.add_systems(OnExit(GameState::Play), (PlayStatePlugin::on_exit, replay_playback::cleanup_replay_playback));
 
         // Bevy's system is not the best (or i'm misusing?)
         // This is required so that the systems are executed in the correct order.
-        let update_cursor = cursor::on_update.run_if(in_state(GameState::Play));
-        let update_notes = note::on_update.run_if(in_state(GameState::Play));
+        // The cursor and notes are frozen while the game is paused.
+        let update_pause = (pause::on_pause_keys, pause::on_pause_button, pause::update_pause).chain().run_if(in_state(GameState::Play));
+        let update_replay = (replay_playback::on_replay_keys, replay_playback::update_replay_playback).chain().run_if(in_state(GameState::Play)).run_if(replay_playback::is_watching);
+        let update_cursor = cursor::on_update.run_if(in_state(GameState::Play)).run_if(pause::is_not_frozen);
+        let update_notes = note::on_update.run_if(in_state(GameState::Play)).run_if(pause::is_not_frozen).run_if(replay_playback::is_not_held);
         let update_hud = hud::on_update.run_if(in_state(GameState::Play));
         let update_win_cursor = PlayStatePlugin::update_window_cursor_state.run_if(in_state(GameState::Play));
         app.add_systems(Update, (
+            update_pause.before(cursor::on_update),
+            update_replay.after(pause::update_pause).before(cursor::on_update),
             update_cursor.before(note::on_update),
             update_notes.before(hud::on_update),
             update_hud.after(note::on_update),
-            update_win_cursor,
-            poll_map_load_play
+            update_win_cursor.after(pause::update_pause),
+            poll_map_load_play,
+            sound::prepare_song.run_if(in_state(GameState::Loading)).run_if(resource_exists::<SongPreparation>)
         ));
+        app.add_systems(OnExit(GameState::Loading), sound::cleanup_song_preparation);
+        app.init_resource::<StretchedSongs>();
     }
 }
\ No newline at end of file
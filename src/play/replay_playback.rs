@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use bevy::{asset::Assets, ecs::{component::Component, entity::Entity, query::{With, Without}, system::{Commands, Query, Res, ResMut, Resource}}, math::{primitives::Rectangle, Vec2, Vec3}, render::{color::Color, mesh::Mesh, view::Visibility}, text::{Text, TextStyle}, time::{Time, Virtual}, transform::components::Transform, ui::{node_bundles::TextBundle, PositionType, Style, Val}, utils::default};
+use bevy_kira_audio::prelude::*;
+use bevy_mod_billboard::{BillboardMeshHandle, BillboardTextureBundle, BillboardTextureHandle};
+
+use crate::{engine::simulate, input::{MenuAction, MenuInput}, replay::ReplayFrame, settings::Settings, startup::GlobalAssets};
+
+use super::{note::{get_play_time_ms, PlayNote}, pause::PlayPause, play_state::{InPlay, PlayStateData}};
+
+const SEEK_STEP_MS: i128 = 5000;
+const MIN_WATCH_SPEED: f32 = 0.25;
+const MAX_WATCH_SPEED: f32 = 2.;
+const WATCH_SPEED_STEP: f32 = 0.25;
+const TRAIL_DOTS: usize = 16;
+const TRAIL_STEP_MS: i128 = 10;// Time between two dots of the cursor trail
+const TRAIL_DOT_SIZE: f32 = 0.2;
+
+// Controls for watching a replay, only inserted while one is watched.
+// The watch speed scales the whole game clock, the replay and its song stay in sync with each other.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    // Play time the replay is held at while paused
+    paused_ms: Option<i128>,
+    speed: f32,
+    show_trail: bool,
+}
+
+#[derive(Component)]
+pub struct ReplayTrailDot {
+    index: usize,
+}
+
+#[derive(Component)]
+pub struct ReplayInfoText;
+
+pub fn is_watching(data: Option<Res<PlayStateData>>) -> bool {
+    return data.map_or(false, |data| data.watching.is_some());
+}
+
+// The notes don't move while a watched replay is paused
+pub fn is_not_held(playback: Option<Res<ReplayPlayback>>) -> bool {
+    return playback.map_or(true, |playback| playback.paused_ms.is_none());
+}
+
+// Play time of the watched replay, it stops while the replay is paused
+pub fn get_watch_time_ms(data: &PlayStateData, playback: Option<&ReplayPlayback>, now: Duration, start_finish_wait: i128) -> i128 {
+    return playback.and_then(|playback| playback.paused_ms).unwrap_or_else(|| get_play_time_ms(data, now, start_finish_wait));
+}
+
+// Cursor position in the replay at a play time, between the two closest frames
+pub fn replay_position(frames: &[ReplayFrame], time_ms: i128) -> Vec2 {
+    let next = frames.partition_point(|frame| (frame.time_ms as i128) <= time_ms);
+    if next == 0 || next == frames.len() {
+        return frames.get(next.min(frames.len().saturating_sub(1))).map_or(Vec2::ZERO, |frame| Vec2::new(frame.x, frame.y));
+    }
+    let (a, b) = (frames[next - 1], frames[next]);
+    let t = (time_ms - a.time_ms as i128) as f32 / (b.time_ms - a.time_ms).max(1) as f32;
+    return Vec2::new(a.x, a.y).lerp(Vec2::new(b.x, b.y), t);
+}
+
+pub fn init_replay_playback(
+    data: Res<PlayStateData>,
+    globals: Res<GlobalAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut commands: Commands
+) {
+    commands.insert_resource(ReplayPlayback {
+        paused_ms: None,
+        speed: 1.,
+        show_trail: false,
+    });
+
+    // Recorded cursor positions right before the current one, smaller the older they are
+    let mesh = meshes.add(Rectangle::new(TRAIL_DOT_SIZE, TRAIL_DOT_SIZE));
+    for index in 0..TRAIL_DOTS {
+        let scale = 1. - index as f32 / TRAIL_DOTS as f32;
+        commands.spawn((BillboardTextureBundle {
+            transform: Transform::from_translation(Vec3::new(0., 0., 0.01)).with_scale(Vec3::splat(scale)),
+            texture: BillboardTextureHandle(globals.cursor.clone()),
+            mesh: BillboardMeshHandle(mesh.clone()),
+            visibility: Visibility::Hidden,
+            ..default()
+        }, InPlay, ReplayTrailDot { index }));
+    }
+
+    let title = data.watching.as_ref().map_or(String::new(), |replay| replay.map_title.clone());
+    commands.spawn((TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.),
+            top: Val::Px(10.),
+            ..default()
+        },
+        text: Text::from_section(format!("Watching a replay of {0}", title), TextStyle {
+            font_size: 20.,
+            color: Color::rgb(0.8, 0.8, 0.8),
+            font: globals.main_font.clone(),
+        }),
+        ..default()
+    }, InPlay, ReplayInfoText));
+}
+
+pub fn on_replay_keys(
+    input: MenuInput,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    pause: Res<PlayPause>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut data: ResMut<PlayStateData>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    q_notes: Query<Entity, With<PlayNote>>,
+    mut commands: Commands
+) {
+    if pause.is_frozen() {
+        return;
+    }
+    let now = time.elapsed();
+    let watch_time_ms = get_watch_time_ms(&data, Some(&playback), now, settings.start_finish_wait);
+
+    if input.just_pressed(MenuAction::ReplayPause) {
+        match playback.paused_ms {
+            Some(paused_ms) => {
+                // Continue from where it was held, the song is resumed by `note::on_update`
+                data.time_offset_ms += paused_ms - get_play_time_ms(&data, now, settings.start_finish_wait);
+                playback.paused_ms = None;
+            }
+            None => {
+                playback.paused_ms = Some(watch_time_ms);
+                if let Some(instance) = audio_instances.get_mut(&data.song) {
+                    instance.pause(AudioTween::default());
+                }
+            }
+        }
+    }
+
+    let seek_steps = input.just_pressed(MenuAction::Right) as i128 - input.just_pressed(MenuAction::Left) as i128;
+    if seek_steps != 0 {
+        let target_ms = seek(watch_time_ms + seek_steps * SEEK_STEP_MS, &mut data, settings.start_finish_wait, &q_notes, &mut commands);
+        match playback.paused_ms {
+            Some(_) => playback.paused_ms = Some(target_ms),
+            None => data.time_offset_ms += target_ms - watch_time_ms,
+        }
+    }
+
+    let speed_steps = input.just_pressed(MenuAction::Up) as i32 - input.just_pressed(MenuAction::Down) as i32;
+    if speed_steps != 0 {
+        playback.speed = (playback.speed + speed_steps as f32 * WATCH_SPEED_STEP).clamp(MIN_WATCH_SPEED, MAX_WATCH_SPEED);
+        virtual_time.set_relative_speed(playback.speed);
+        if let Some(instance) = audio_instances.get_mut(&data.song) {
+            instance.set_playback_rate((data.audio_rate * playback.speed) as f64, AudioTween::default());
+        }
+    }
+
+    if input.just_pressed(MenuAction::ReplayTrail) {
+        playback.show_trail = !playback.show_trail;
+    }
+}
+
+// Rebuilds the play as it was at the target time from the recorded frames, returns the time it seeked to
+fn seek(target_ms: i128, data: &mut PlayStateData, start_finish_wait: i128, q_notes: &Query<Entity, With<PlayNote>>, commands: &mut Commands) -> i128 {
+    let Some(replay) = &data.watching else {
+        return target_ms;
+    };
+    let target_ms = target_ms.clamp(0, data.play.last_note_ms() + start_finish_wait);
+    // The frames from the target on are judged by `note::on_update`
+    let frames: Vec<ReplayFrame> = replay.frames.iter().copied().take_while(|frame| (frame.time_ms as i128) < target_ms).collect();
+
+    for entity in q_notes.iter() {
+        commands.entity(entity).despawn();
+    }
+    data.play = simulate(&data.note_data.0, &data.modifiers, data.play.windows(), &frames);
+    data.note_tracker = data.play.note_tracker();
+    return target_ms;
+}
+
+pub fn update_replay_playback(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    data: Res<PlayStateData>,
+    playback: Res<ReplayPlayback>,
+    mut q_dots: Query<(&ReplayTrailDot, &mut Transform, &mut Visibility)>,
+    mut q_info: Query<&mut Text, (With<ReplayInfoText>, Without<ReplayTrailDot>)>
+) {
+    let Some(replay) = &data.watching else {
+        return;
+    };
+    let watch_time_ms = get_watch_time_ms(&data, Some(&playback), time.elapsed(), settings.start_finish_wait);
+    for (dot, mut transform, mut visibility) in &mut q_dots {
+        let position = replay_position(&replay.frames, watch_time_ms - (dot.index as i128 + 1) * TRAIL_STEP_MS);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        *visibility = if playback.show_trail { Visibility::Inherited } else { Visibility::Hidden };
+    }
+
+    if let Ok(mut info) = q_info.get_single_mut() {
+        info.sections[0].value = format!(
+            "Watching a replay of {0}{1}\n{2:.2}x - Space pause, Left/Right seek, Up/Down speed, T trail {3}",
+            replay.map_title,
+            if playback.paused_ms.is_some() { " (paused)" } else { "" },
+            playback.speed,
+            if playback.show_trail { "on" } else { "off" }
+        );
+    }
+}
+
+// The game clock is shared with the rest of the game, so its speed goes back to normal
+pub fn cleanup_replay_playback(mut virtual_time: ResMut<Time<Virtual>>, mut commands: Commands) {
+    virtual_time.set_relative_speed(1.);
+    commands.remove_resource::<ReplayPlayback>();
+}
@@ -1,14 +1,128 @@
-use bevy::{ecs::system::{Res, ResMut}, time::Time};
+use bevy::{asset::{AssetServer, Assets, Handle}, ecs::system::{Commands, Res, ResMut, Resource}, log::info, tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task}, time::Time};
 use bevy_kira_audio::prelude::*;
+use kira::sound::static_sound::StaticSoundData;
 
-use super::play_state::PlayStateData;
+use crate::{library::apply_metadata, loading::LoadingTracker, map::MapMetadata, settings::Settings};
+
+use super::{play_state::PlayStateData, time_stretch::time_stretch};
+
+pub const SONG_JOB: &str = "song";
+// Stretching takes a while, the last few stretched songs are kept to restart quickly
+const STRETCHED_SONGS_KEPT: usize = 4;
+
+// The song of a map that is loading, it is only known once the map information is loaded
+// because the map.json of a map can be the only place with its audio path.
+// With pitch correction the song is then time stretched in the background.
+#[derive(Resource)]
+pub struct SongPreparation {
+    metadata: Option<Handle<MapMetadata>>,
+    loading: bool,
+    stretching: Option<Task<StaticSoundData>>,
+}
+
+impl SongPreparation {
+    pub fn new(metadata: Option<Handle<MapMetadata>>) -> SongPreparation {
+        SongPreparation {
+            metadata,
+            loading: false,
+            stretching: None,
+        }
+    }
+}
+
+// Time stretched songs by their original song and speed, the original is kept so it isn't loaded under another id
+#[derive(Resource, Default)]
+pub struct StretchedSongs {
+    songs: Vec<(Handle<AudioSource>, u32, Handle<AudioSource>)>,
+}
+
+impl StretchedSongs {
+    fn get(&self, song: &Handle<AudioSource>, speed: f32) -> Option<Handle<AudioSource>> {
+        return self.songs.iter()
+            .find(|(original, bits, _)| original.id() == song.id() && *bits == speed.to_bits())
+            .map(|(_, _, stretched)| stretched.clone());
+    }
+
+    fn insert(&mut self, song: Handle<AudioSource>, speed: f32, stretched: Handle<AudioSource>) {
+        if self.songs.len() >= STRETCHED_SONGS_KEPT {
+            self.songs.remove(0);
+        }
+        self.songs.push((song, speed.to_bits(), stretched));
+    }
+}
+
+pub fn prepare_song(
+    mut preparation: ResMut<SongPreparation>,
+    mut data: ResMut<PlayStateData>,
+    mut tracker: ResMut<LoadingTracker>,
+    metadatas: Res<Assets<MapMetadata>>,
+    server: Res<AssetServer>,
+    mut audio_sources: ResMut<Assets<AudioSource>>,
+    mut stretched_songs: ResMut<StretchedSongs>,
+    mut commands: Commands
+) {
+    if !preparation.loading {
+        // A map information that fails to load is shown by the loading screen
+        if let Some(handle) = &preparation.metadata {
+            let Some(metadata) = metadatas.get(handle) else {
+                return;
+            };
+            apply_metadata(&mut data.map, metadata, handle, &server);
+        }
+        if data.map.audio_path.is_empty() {
+            tracker.fail(SONG_JOB, "The map has no audio file");
+            commands.remove_resource::<SongPreparation>();
+            return;
+        }
+        data.audio = server.load::<AudioSource>(&data.map.audio_path);
+        tracker.add(SONG_JOB, &data.audio);
+        preparation.loading = true;
+        if !data.modifiers.pitch_correct || data.play_speed == 1. {
+            tracker.finish(SONG_JOB);
+            commands.remove_resource::<SongPreparation>();
+            return;
+        }
+    }
+
+    let stretched = if let Some(task) = &mut preparation.stretching {
+        let Some(sound) = block_on(future::poll_once(task)) else {
+            return;
+        };
+        let stretched = audio_sources.add(AudioSource { sound });
+        stretched_songs.insert(data.audio.clone(), data.play_speed, stretched.clone());
+        stretched
+    } else if let Some(stretched) = stretched_songs.get(&data.audio, data.play_speed) {
+        stretched
+    } else {
+        // A song that fails to load is shown by the loading screen
+        if let Some(source) = audio_sources.get(&data.audio) {
+            info!("Time stretching the song to {0}x", data.play_speed);
+            let sound = source.sound.clone();
+            let speed = data.play_speed;
+            preparation.stretching = Some(AsyncComputeTaskPool::get().spawn(async move { time_stretch(&sound, speed) }));
+        }
+        return;
+    };
+
+    // The stretched song already has the play speed, it is played at its normal rate
+    data.audio = stretched;
+    data.audio_rate = 1.;
+    tracker.finish(SONG_JOB);
+    commands.remove_resource::<SongPreparation>();
+}
+
+pub fn cleanup_song_preparation(mut commands: Commands) {
+    commands.remove_resource::<SongPreparation>();
+}
 
 pub fn init_sound(mut data: ResMut<PlayStateData>, 
     time: ResMut<Time>, 
+    settings: Res<Settings>,
     kira_audio: Res<Audio>) {
     data.start_time = time.elapsed();
-    let mut command = kira_audio.play(data.map.audio.clone());
+    let mut command = kira_audio.play(data.audio.clone());
     //command.fade_in(AudioTween::new(Duration::from_secs(2), AudioEasing::OutPowi(2)));
-    command.with_playback_rate(data.play_speed as f64);
+    command.with_playback_rate(data.audio_rate as f64);
+    command.with_volume(settings.music_volume());
     data.song = command.handle().clone();
-}
\ No newline at end of file
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use kira::{dsp::Frame, sound::static_sound::StaticSoundData};
+
+// WSOLA (waveform similarity overlap-add) time stretching, used to change the speed of a song without changing its pitch.
+// Windows of the song are overlapped at the new speed, each window is slightly moved so that it lines up
+// with the end of the previous one, which avoids most of the phasing of a plain overlap-add.
+const WINDOW_MS: f32 = 40.;
+const SEEK_MS: f32 = 8.;
+// Only every Nth sample/offset is compared when looking for the best alignment, a full search would be way too slow for whole songs
+const CORRELATION_STEP: usize = 8;
+const SEEK_STEP: usize = 2;
+
+pub fn time_stretch(data: &StaticSoundData, speed: f32) -> StaticSoundData {
+    let frames = stretch_frames(&data.frames, data.sample_rate, speed);
+    return StaticSoundData {
+        sample_rate: data.sample_rate,
+        frames: Arc::from(frames),
+        settings: data.settings,
+    };
+}
+
+fn stretch_frames(input: &[Frame], sample_rate: u32, speed: f32) -> Vec<Frame> {
+    let window = ((WINDOW_MS / 1000. * sample_rate as f32) as usize).max(4) & !1;
+    let hop_out = window / 2;
+    let hop_in = hop_out as f32 * speed;
+    let seek = (SEEK_MS / 1000. * sample_rate as f32) as usize;
+    if input.len() < window * 2 || speed <= 0. {
+        return input.to_vec();
+    }
+
+    // Periodic hann window, two of them overlapping by half always add up to 1
+    let weights: Vec<f32> = (0..window)
+        .map(|i| 0.5 - 0.5 * (2. * std::f32::consts::PI * i as f32 / window as f32).cos())
+        .collect();
+    let mono: Vec<f32> = input.iter().map(|frame| frame.left + frame.right).collect();
+
+    let output_len = (input.len() as f32 / speed) as usize;
+    let mut output = vec![Frame::ZERO; output_len + window];
+    let mut previous: Option<usize> = None;
+    let mut out_pos = 0;
+    while out_pos < output_len {
+        let nominal = (out_pos as f32 / hop_out as f32 * hop_in) as usize;
+        if nominal + window >= input.len() {
+            break;
+        }
+        let in_pos = match previous {
+            // What would have followed the previous window if nothing was stretched
+            Some(previous) if previous + hop_out + window < input.len() => best_alignment(&mono, previous + hop_out, nominal, seek, hop_out, window),
+            _ => nominal,
+        };
+        for i in 0..window {
+            let frame = input[in_pos + i];
+            let weight = weights[i];
+            output[out_pos + i].left += frame.left * weight;
+            output[out_pos + i].right += frame.right * weight;
+        }
+        previous = Some(in_pos);
+        out_pos += hop_out;
+    }
+    output.truncate(output_len);
+    return output;
+}
+
+// The position around `nominal` whose start looks the most like the samples at `target`
+fn best_alignment(mono: &[f32], target: usize, nominal: usize, seek: usize, overlap: usize, window: usize) -> usize {
+    let start = nominal.saturating_sub(seek);
+    let end = (nominal + seek).min(mono.len() - window);
+    let mut best = nominal;
+    let mut best_correlation = f32::MIN;
+    for candidate in (start..=end).step_by(SEEK_STEP) {
+        let mut correlation = 0.;
+        for i in (0..overlap).step_by(CORRELATION_STEP) {
+            correlation += mono[candidate + i] * mono[target + i];
+        }
+        if correlation > best_correlation {
+            best_correlation = correlation;
+            best = candidate;
+        }
+    }
+    return best;
+}
+
+#[cfg(test)]
+mod tests {
+    use kira::dsp::Frame;
+
+    use super::{stretch_frames, WINDOW_MS};
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    // A 440Hz tone with a slower one on the right channel
+    fn tone(length: usize) -> Vec<Frame> {
+        return (0..length)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                Frame::new((t * 440. * std::f32::consts::TAU).sin(), (t * 110. * std::f32::consts::TAU).sin() * 0.5)
+            })
+            .collect();
+    }
+
+    fn window() -> usize {
+        return (WINDOW_MS / 1000. * SAMPLE_RATE as f32) as usize;
+    }
+
+    #[test]
+    fn output_length_follows_the_speed() {
+        let input = tone(SAMPLE_RATE as usize * 2);
+        for speed in [0.5, 0.75, 1., 1.3, 2.] {
+            let output = stretch_frames(&input, SAMPLE_RATE, speed);
+            let expected = input.len() as f32 / speed;
+            assert!((output.len() as f32 - expected).abs() <= 1., "{0}x: {1} frames, expected {2}", speed, output.len(), expected);
+            // The song is still there in the middle, not only silence
+            let middle = &output[output.len() / 2..output.len() / 2 + window()];
+            let loudest = middle.iter().map(|frame| frame.left.abs()).fold(0., f32::max);
+            assert!(loudest > 0.5, "{0}x: loudest {1}", speed, loudest);
+        }
+    }
+
+    #[test]
+    fn short_input_is_unchanged() {
+        let input = tone(window() * 2 - 1);
+        for speed in [0.5, 2.] {
+            let output = stretch_frames(&input, SAMPLE_RATE, speed);
+            assert_eq!(output.len(), input.len());
+            assert!(output.iter().zip(&input).all(|(a, b)| a.left == b.left && a.right == b.right), "{0}x", speed);
+        }
+    }
+
+    #[test]
+    fn any_length_stretches_without_going_out_of_bounds() {
+        // Lengths around the edge cases of the windows and the alignment search
+        let window = window();
+        for length in (window * 2..window * 8).step_by(7).chain([SAMPLE_RATE as usize, SAMPLE_RATE as usize + 1]) {
+            let input = tone(length);
+            for speed in [0.5, 2.] {
+                let output = stretch_frames(&input, SAMPLE_RATE, speed);
+                assert_eq!(output.len(), (length as f32 / speed) as usize, "{0} frames at {1}x", length, speed);
+            }
+        }
+    }
+}
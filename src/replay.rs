@@ -0,0 +1,399 @@
+use std::{fs, io::{self, BufReader, BufWriter, Read, Write}, path::{Path, PathBuf}};
+
+use crate::{engine::judgement::JudgementWindows, modifiers::{Modifiers, MAX_SPEED, MIN_SPEED, MODIFIERS}, storage};
+#[cfg(feature = "client")]
+use crate::settings::Settings;
+
+const REPLAYS_SUBDIR: &str = "replays";
+pub const REPLAY_EXTENSION: &str = "sarp";
+const REPLAY_MAGIC: &[u8; 4] = b"SARP";
+// Version 1 had no judgement windows, they are the default ones
+const REPLAY_VERSION: u16 = 2;
+// Anything longer is not a string written by the game
+const MAX_STRING_LENGTH: usize = 4096;
+
+pub use crate::engine::ReplayFrame;
+
+// A recorded play, enough to watch it again and to check its score.
+//
+// Binary format, all numbers little endian:
+// magic "SARP", version u16,
+// map id, map title (u16 length + utf-8 each), date u64,
+// approach rate i32, approach distance f32, fade in i32, start/finish wait i32,
+// perfect window i32, great window i32 (since version 2),
+// modifier flags u8 (Modifier::flag: no fail, hard rock, hidden, ghost, mirror, autoplay, flip from bit 0), speed f32, pitch correct u8,
+// score i64, accuracy f32, hits u32, misses u32, max combo u32, failed u8,
+// frame count u32, then every frame as time i32, x f32, y f32
+#[derive(Clone)]
+pub struct Replay {
+    pub map_id: String,
+    pub map_title: String,
+    // Unix time in seconds
+    pub date: u64,
+    pub approach_rate: i128,
+    pub approach_distance: f32,
+    pub fade_in: i128,
+    pub start_finish_wait: i128,
+    pub windows: JudgementWindows,
+    pub modifiers: Modifiers,
+    // The result of the play when it was recorded
+    pub score: i128,
+    pub accuracy: f32,
+    pub hits: i128,
+    pub misses: i128,
+    pub max_combo: i128,
+    pub failed: bool,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    // Takes the player settings that change how the play looked
+    #[cfg(feature = "client")]
+    pub fn new(map_id: String, map_title: String, date: u64, settings: &Settings, modifiers: Modifiers) -> Replay {
+        Replay {
+            map_id,
+            map_title,
+            date,
+            approach_rate: settings.approach_rate,
+            approach_distance: settings.approach_distance,
+            fade_in: settings.fade_in,
+            start_finish_wait: settings.start_finish_wait,
+            windows: settings.judgement_windows(),
+            modifiers,
+            score: 0,
+            accuracy: 0.,
+            hits: 0,
+            misses: 0,
+            max_combo: 0,
+            failed: false,
+            frames: vec![],
+        }
+    }
+
+    // `copy` tells apart the replays of a map saved in the same second
+    pub fn file_name(&self, copy: u32) -> String {
+        if copy == 0 {
+            return format!("{0}-{1}.{2}", self.map_id, self.date, REPLAY_EXTENSION);
+        }
+        return format!("{0}-{1}-{2}.{3}", self.map_id, self.date, copy, REPLAY_EXTENSION);
+    }
+
+    // Checks that the replay could have been played in the game, a result from anything else can't be trusted
+    pub fn validate(&self) -> Result<(), String> {
+        if self.modifiers.autoplay {
+            return Err("the replay was played by autoplay".to_owned());
+        }
+        let speed = self.modifiers.speed;
+        if !speed.is_finite() || !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+            return Err(format!("speed {0} is not between {1}x and {2}x", speed, MIN_SPEED, MAX_SPEED));
+        }
+        if !self.windows.is_valid() {
+            return Err(format!("the judgement windows (Perfect {0}ms, Great {1}ms) can't be picked in the game", self.windows.perfect_ms, self.windows.great_ms));
+        }
+        return Ok(());
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(REPLAY_MAGIC)?;
+        writer.write_all(&REPLAY_VERSION.to_le_bytes())?;
+        write_string(writer, &self.map_id)?;
+        write_string(writer, &self.map_title)?;
+        writer.write_all(&self.date.to_le_bytes())?;
+
+        writer.write_all(&(self.approach_rate as i32).to_le_bytes())?;
+        writer.write_all(&self.approach_distance.to_le_bytes())?;
+        writer.write_all(&(self.fade_in as i32).to_le_bytes())?;
+        writer.write_all(&(self.start_finish_wait as i32).to_le_bytes())?;
+        writer.write_all(&(self.windows.perfect_ms as i32).to_le_bytes())?;
+        writer.write_all(&(self.windows.great_ms as i32).to_le_bytes())?;
+
+        let mut flags: u8 = 0;
+        for modifier in MODIFIERS {
+            if self.modifiers.is_enabled(modifier) {
+                flags |= modifier.flag();
+            }
+        }
+        writer.write_all(&[flags])?;
+        writer.write_all(&self.modifiers.speed.to_le_bytes())?;
+        writer.write_all(&[self.modifiers.pitch_correct as u8])?;
+
+        writer.write_all(&(self.score as i64).to_le_bytes())?;
+        writer.write_all(&self.accuracy.to_le_bytes())?;
+        writer.write_all(&(self.hits as u32).to_le_bytes())?;
+        writer.write_all(&(self.misses as u32).to_le_bytes())?;
+        writer.write_all(&(self.max_combo as u32).to_le_bytes())?;
+        writer.write_all(&[self.failed as u8])?;
+
+        writer.write_all(&(self.frames.len() as u32).to_le_bytes())?;
+        for frame in &self.frames {
+            writer.write_all(&frame.time_ms.to_le_bytes())?;
+            writer.write_all(&frame.x.to_le_bytes())?;
+            writer.write_all(&frame.y.to_le_bytes())?;
+        }
+        return Ok(());
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<Replay> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != REPLAY_MAGIC {
+            return Err(invalid_data("not a replay file".to_owned()));
+        }
+        let version = u16::from_le_bytes(read_array(reader)?);
+        if version == 0 || version > REPLAY_VERSION {
+            return Err(invalid_data(format!("unsupported replay version {0}", version)));
+        }
+        let map_id = read_string(reader)?;
+        let map_title = read_string(reader)?;
+        let date = u64::from_le_bytes(read_array(reader)?);
+
+        let approach_rate = i32::from_le_bytes(read_array(reader)?) as i128;
+        let approach_distance = f32::from_le_bytes(read_array(reader)?);
+        let fade_in = i32::from_le_bytes(read_array(reader)?) as i128;
+        let start_finish_wait = i32::from_le_bytes(read_array(reader)?) as i128;
+        let mut windows = JudgementWindows::default();
+        if version >= 2 {
+            windows.perfect_ms = i32::from_le_bytes(read_array(reader)?) as i128;
+            windows.great_ms = i32::from_le_bytes(read_array(reader)?) as i128;
+        }
+
+        let [flags] = read_array(reader)?;
+        let mut modifiers = Modifiers::default();
+        for modifier in MODIFIERS {
+            if flags & modifier.flag() != 0 {
+                modifiers.toggle(modifier);
+            }
+        }
+        modifiers.speed = f32::from_le_bytes(read_array(reader)?);
+        let [pitch_correct] = read_array(reader)?;
+        modifiers.pitch_correct = pitch_correct != 0;
+
+        let score = i64::from_le_bytes(read_array(reader)?) as i128;
+        let accuracy = f32::from_le_bytes(read_array(reader)?);
+        let hits = u32::from_le_bytes(read_array(reader)?) as i128;
+        let misses = u32::from_le_bytes(read_array(reader)?) as i128;
+        let max_combo = u32::from_le_bytes(read_array(reader)?) as i128;
+        let [failed] = read_array(reader)?;
+
+        let frame_count = u32::from_le_bytes(read_array(reader)?) as usize;
+        // Not trusting the count for the allocation, a broken file would otherwise ask for gigabytes
+        let mut frames = Vec::with_capacity(frame_count.min(1 << 16));
+        for _ in 0..frame_count {
+            frames.push(ReplayFrame {
+                time_ms: i32::from_le_bytes(read_array(reader)?),
+                x: f32::from_le_bytes(read_array(reader)?),
+                y: f32::from_le_bytes(read_array(reader)?),
+            });
+        }
+
+        return Ok(Replay {
+            map_id,
+            map_title,
+            date,
+            approach_rate,
+            approach_distance,
+            fade_in,
+            start_finish_wait,
+            windows,
+            modifiers,
+            score,
+            accuracy,
+            hits,
+            misses,
+            max_combo,
+            failed: failed != 0,
+            frames,
+        });
+    }
+
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        return Replay::read(&mut BufReader::new(fs::File::open(path)?));
+    }
+
+    // Saved in the replays folder of the user data directory, returns where it was written
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let Some(dir) = replays_dir() else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no data directory on this platform"));
+        };
+        fs::create_dir_all(&dir)?;
+        // Never overwrites another replay
+        let mut copy = 0;
+        let (path, file) = loop {
+            let path = dir.join(self.file_name(copy));
+            match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => break (path, file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => copy += 1,
+                Err(err) => return Err(err),
+            }
+        };
+        let mut writer = BufWriter::new(file);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        return Ok(path);
+    }
+}
+
+pub fn replays_dir() -> Option<PathBuf> {
+    return storage::data_dir().map(|dir| dir.join(REPLAYS_SUBDIR));
+}
+
+fn invalid_data(message: String) -> io::Error {
+    return io::Error::new(io::ErrorKind::InvalidData, message);
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    return Ok(bytes);
+}
+
+fn write_string(writer: &mut impl Write, string: &str) -> io::Result<()> {
+    // Cut on a character boundary so it can still be read back
+    let mut length = string.len().min(MAX_STRING_LENGTH);
+    while !string.is_char_boundary(length) {
+        length -= 1;
+    }
+    let bytes = &string.as_bytes()[..length];
+    writer.write_all(&(bytes.len() as u16).to_le_bytes())?;
+    return writer.write_all(bytes);
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let length = u16::from_le_bytes(read_array(reader)?) as usize;
+    if length > MAX_STRING_LENGTH {
+        return Err(invalid_data(format!("string of {0} bytes", length)));
+    }
+    let mut bytes = vec![0u8; length];
+    reader.read_exact(&mut bytes)?;
+    return String::from_utf8(bytes).map_err(|err| invalid_data(err.to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{engine::judgement::JudgementWindows, modifiers::Modifiers};
+
+    use super::{Replay, ReplayFrame, MAX_STRING_LENGTH, REPLAY_MAGIC, REPLAY_VERSION};
+
+    fn replay() -> Replay {
+        return Replay {
+            map_id: "0123456789abcdef".to_owned(),
+            map_title: "Artist - Sông".to_owned(),
+            date: 1700000000,
+            approach_rate: 450,
+            approach_distance: 12.5,
+            fade_in: 150,
+            start_finish_wait: 1200,
+            windows: JudgementWindows { perfect_ms: 30, great_ms: 80 },
+            modifiers: Modifiers { hard_rock: true, mirror: true, flip: true, speed: 1.3, pitch_correct: true, ..Modifiers::default() },
+            score: 123456,
+            accuracy: 97.25,
+            hits: 40,
+            misses: 2,
+            max_combo: 31,
+            failed: true,
+            frames: vec![
+                ReplayFrame { time_ms: -250, x: 0., y: 0. },
+                ReplayFrame { time_ms: 16, x: 0.5, y: -0.25 },
+                ReplayFrame { time_ms: 33, x: -1.75, y: 1.5 },
+            ],
+        };
+    }
+
+    fn to_bytes(replay: &Replay) -> Vec<u8> {
+        let mut bytes = vec![];
+        replay.write(&mut bytes).unwrap();
+        return bytes;
+    }
+
+    fn read_err(bytes: &[u8]) -> io::Error {
+        match Replay::read(&mut &bytes[..]) {
+            Ok(_) => panic!("read a broken replay"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn written_replays_read_back() {
+        let original = replay();
+        let read = Replay::read(&mut &to_bytes(&original)[..]).unwrap();
+        assert_eq!((&read.map_id, &read.map_title, read.date), (&original.map_id, &original.map_title, original.date));
+        assert_eq!(
+            (read.approach_rate, read.approach_distance, read.fade_in, read.start_finish_wait),
+            (original.approach_rate, original.approach_distance, original.fade_in, original.start_finish_wait)
+        );
+        assert_eq!(read.windows, original.windows);
+        assert!(read.modifiers == original.modifiers);
+        assert_eq!(
+            (read.score, read.accuracy, read.hits, read.misses, read.max_combo, read.failed),
+            (original.score, original.accuracy, original.hits, original.misses, original.max_combo, original.failed)
+        );
+        assert_eq!(read.frames, original.frames);
+    }
+
+    #[test]
+    fn version_1_replays_have_the_default_windows() {
+        let original = replay();
+        let mut bytes = to_bytes(&original);
+        bytes[4..6].copy_from_slice(&1u16.to_le_bytes());
+        // The windows come right after the start/finish wait
+        let windows_start = 4 + 2 + (2 + original.map_id.len()) + (2 + original.map_title.len()) + 8 + 16;
+        bytes.drain(windows_start..windows_start + 8);
+
+        let read = Replay::read(&mut &bytes[..]).unwrap();
+        assert_eq!(read.windows, JudgementWindows::default());
+        assert_eq!(read.start_finish_wait, original.start_finish_wait);
+        assert!(read.modifiers == original.modifiers);
+        assert_eq!(read.score, original.score);
+        assert_eq!(read.frames, original.frames);
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let mut bytes = to_bytes(&replay());
+        bytes[..4].copy_from_slice(b"SSPM");
+        assert_eq!(read_err(&bytes).kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn replays_from_newer_versions_are_rejected() {
+        let mut bytes = to_bytes(&replay());
+        bytes[4..6].copy_from_slice(&(REPLAY_VERSION + 1).to_le_bytes());
+        let err = read_err(&bytes);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("version"), "{0}", err);
+    }
+
+    #[test]
+    fn truncated_frames_are_errors() {
+        let bytes = to_bytes(&replay());
+        // In the middle of the last frame, then right after the frame count
+        for length in [bytes.len() - 5, bytes.len() - 3 * 12] {
+            assert_eq!(read_err(&bytes[..length]).kind(), io::ErrorKind::UnexpectedEof, "{0} bytes", length);
+        }
+    }
+
+    #[test]
+    fn too_long_strings_are_rejected() {
+        let mut bytes = REPLAY_MAGIC.to_vec();
+        bytes.extend_from_slice(&REPLAY_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(MAX_STRING_LENGTH as u16 + 1).to_le_bytes());
+        bytes.resize(bytes.len() + MAX_STRING_LENGTH + 1, b'a');
+        assert_eq!(read_err(&bytes).kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn too_long_strings_are_cut_when_written() {
+        let original = Replay { map_title: "é".repeat(MAX_STRING_LENGTH), ..replay() };
+        let read = Replay::read(&mut &to_bytes(&original)[..]).unwrap();
+        assert_eq!(read.map_title, "é".repeat(MAX_STRING_LENGTH / 2));
+    }
+
+    #[test]
+    fn file_names_are_unique_per_copy() {
+        let replay = replay();
+        assert_eq!(replay.file_name(0), "0123456789abcdef-1700000000.sarp");
+        assert_eq!(replay.file_name(2), "0123456789abcdef-1700000000-2.sarp");
+    }
+}
@@ -0,0 +1,540 @@
+use std::path::PathBuf;
+
+use bevy::{app::{App, Plugin, Update}, asset::{Assets, Handle}, core_pipeline::core_2d::Camera2dBundle, ecs::{component::Component, entity::Entity, query::{Changed, With}, schedule::{common_conditions::{in_state, not}, IntoSystemConfigs, NextState, OnEnter, OnExit}, system::{Commands, Query, Res, ResMut, Resource}}, hierarchy::{BuildChildren, ChildBuilder, DespawnRecursiveExt}, log::{info, warn}, render::{camera::{Camera, ClearColorConfig}, color::Color}, text::{Font, Text, TextSection, TextStyle}, ui::{node_bundles::{ButtonBundle, NodeBundle, TextBundle}, widget::Button, AlignItems, BackgroundColor, FlexDirection, Interaction, JustifyContent, Style, UiRect, Val}, utils::default};
+use num_format::{Locale, ToFormattedString};
+
+use crate::{engine::{judgement::{HitResult, JudgementWindows, NoteJudgement, NOTE_EARLY_HIT_WINDOW, NOTE_LATE_HIT_WINDOW}, scoring::{calc_play_grade, get_accuracy_of, get_play_grade_name}}, input::{MenuAction, MenuInput}, play::{hud::{get_hit_result_color, get_play_grade_color}, play_state::{MapLoadPlayResource, PlayStateData}}, map::{format_length, NoteData}, modifiers::Modifiers, replay::Replay, scores::{format_score_line, map_id, now_unix, ScoreDatabase, ScoreRecord}, settings::Settings, startup::GlobalAssets, state::GameState};
+
+const HISTOGRAM_BINS: usize = 20;
+const ACCURACY_GRAPH_SAMPLES: usize = 50;
+const GRAPH_WIDTH: f32 = 500.;
+const GRAPH_HEIGHT: f32 = 150.;
+const PANEL_COLOR: Color = Color::rgb(0.08, 0.08, 0.08);
+const TOP_SCORES_SHOWN: usize = 5;
+
+pub struct ResultsStatePlugin;
+
+#[derive(Component)]
+pub struct OnResults;
+
+// The score of the play shown on the results screen, as saved in the score database
+#[derive(Resource)]
+pub struct ResultsScore {
+    record: ScoreRecord,
+    previous_best: Option<i128>,
+}
+
+// The replay of the play that just ended, failed plays have one too
+#[derive(Resource)]
+pub struct ResultsReplay {
+    pub replay: Replay,
+    // None if it could not be saved
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Component, Clone, Copy, PartialEq)]
+pub enum ResultsButton {
+    Retry,
+    Watch,
+    Back
+}
+
+impl Plugin for ResultsStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Results), (
+            save_replay,
+            (save_score, build_results).chain().run_if(not(has_failed)),
+            build_failed_results.run_if(has_failed)
+        ).chain());
+        app.add_systems(OnExit(GameState::Results), cleanup_results);
+        app.add_systems(Update, (on_results_button, on_results_keys).run_if(in_state(GameState::Results)));
+    }
+}
+
+// Failed plays get their own screen and are not saved
+fn has_failed(data: Res<PlayStateData>) -> bool {
+    return data.play.failed_at_ms.is_some();
+}
+
+fn save_replay(data: Res<PlayStateData>, note_datas: Res<Assets<NoteData>>, settings: Res<Settings>, mut commands: Commands) {
+    // A watched replay is shown again, it is already saved
+    if let Some(replay) = &data.watching {
+        commands.insert_resource(ResultsReplay {
+            replay: replay.clone(),
+            path: None,
+        });
+        return;
+    }
+    // The notes as they are in the map file, not shifted by the map offset
+    let map_id = map_id(note_datas.get(&data.map.notes).unwrap_or(&data.note_data));
+    let mut replay = Replay::new(map_id, data.map.title.clone(), now_unix(), &settings, data.modifiers.clone());
+    replay.score = data.play.score();
+    replay.accuracy = data.play.accuracy();
+    replay.hits = data.play.hits;
+    replay.misses = data.play.misses;
+    replay.max_combo = data.play.max_combo;
+    replay.failed = data.play.failed_at_ms.is_some();
+    replay.frames = data.play.frames.clone();
+    // Autoplay plays can be watched from the results but aren't kept
+    if data.modifiers.autoplay {
+        commands.insert_resource(ResultsReplay {
+            replay,
+            path: None,
+        });
+        return;
+    }
+    let path = match replay.save() {
+        Ok(path) => {
+            info!("Saved the replay to {0}", path.display());
+            Some(path)
+        }
+        Err(err) => {
+            warn!("Could not save the replay: {0}", err);
+            None
+        }
+    };
+    commands.insert_resource(ResultsReplay {
+        replay,
+        path,
+    });
+}
+
+fn save_score(data: Res<PlayStateData>, results_replay: Res<ResultsReplay>, mut db: ResMut<ScoreDatabase>, mut commands: Commands) {
+    let accuracy = data.play.accuracy();
+    let replay = &results_replay.replay;
+    let map_id = replay.map_id.clone();
+    let record = ScoreRecord {
+        map_id: map_id.clone(),
+        map_title: data.map.title.clone(),
+        score: replay.score,
+        accuracy,
+        grade: get_play_grade_name(data.play.grade()),
+        max_combo: data.play.max_combo,
+        hits: data.play.hits,
+        misses: data.play.misses,
+        mods: data.modifiers.acronyms(),
+        speed: data.play_speed,
+        date: replay.date,
+        replay: results_replay.path.as_ref().and_then(|path| path.file_name()).map(|name| name.to_string_lossy().into_owned()),
+    };
+    let previous_best = db.personal_best(&map_id).map(|best| best.score);
+    // Autoplay has to hit every note, anything else is a bug in the autoplay or the judgement
+    if data.modifiers.autoplay && data.play.misses > 0 {
+        warn!("Autoplay missed {0} notes on {1}", data.play.misses, data.map.title);
+    }
+    if data.watching.is_none() && !data.modifiers.autoplay {
+        db.add(record.clone());
+    }
+    commands.insert_resource(ResultsScore {
+        record,
+        previous_best,
+    });
+}
+
+fn build_results(data: Res<PlayStateData>, results: Res<ResultsScore>, db: Res<ScoreDatabase>, globals: Res<GlobalAssets>, mut commands: Commands) {
+    let accuracy = results.record.accuracy;
+    let grade = calc_play_grade(accuracy);
+    let score = results.record.score;
+    let font = globals.main_font.clone();
+
+    commands.spawn((NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        ..default()
+    }, OnResults)).with_children(|builder| {
+        builder.spawn(TextBundle {
+            style: Style {
+                margin: UiRect::px(10., 10., 10., 20.),
+                ..default()
+            },
+            text: Text::from_sections([
+                TextSection::new(data.map.title.clone(), TextStyle {
+                    font_size: 36.,
+                    color: Color::rgb(0.851, 0.247, 0.269),
+                    font: font.clone(),
+                }),
+                TextSection::new(format!("\n{0} - mapped by {1}", data.map.artist, data.map.mapper), TextStyle {
+                    font_size: 20.,
+                    color: Color::rgb(0.7, 0.7, 0.7),
+                    font: font.clone(),
+                }),
+            ]).with_justify(bevy::text::JustifyText::Center),
+            ..default()
+        });
+
+        // Grade and score next to the stats
+        builder.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                margin: UiRect::px(10., 10., 10., 10.),
+                ..default()
+            },
+            ..default()
+        }).with_children(|builder| {
+            builder.spawn(TextBundle {
+                style: Style {
+                    margin: UiRect::px(10., 40., 10., 10.),
+                    ..default()
+                },
+                text: Text::from_sections([
+                    TextSection::new(get_play_grade_name(grade), TextStyle {
+                        font_size: 120.,
+                        color: get_play_grade_color(grade),
+                        font: font.clone(),
+                    }),
+                    TextSection::new("\n".to_owned() + &score.to_formatted_string(&Locale::en), TextStyle {
+                        font_size: 36.,
+                        color: Color::YELLOW,
+                        font: font.clone(),
+                    }),
+                    TextSection::new(build_best_text(&results), TextStyle {
+                        font_size: 18.,
+                        color: Color::rgb(0.7, 0.7, 0.7),
+                        font: font.clone(),
+                    }),
+                ]).with_justify(bevy::text::JustifyText::Center),
+                ..default()
+            });
+            builder.spawn(TextBundle::from_sections([
+                build_stat_section("Accuracy", format!("{:.2}%", accuracy), &font),
+                build_stat_section("Max combo", data.play.max_combo.to_string(), &font),
+                build_stat_section("Hits", format!("{0}/{1}", data.play.hits, data.note_data.0.len()), &font),
+                build_stat_section("Misses", data.play.misses.to_string(), &font),
+                build_stat_section(HitResult::Perfect.name(), data.play.count_judgements(HitResult::Perfect).to_string(), &font),
+                build_stat_section(HitResult::Great.name(), data.play.count_judgements(HitResult::Great).to_string(), &font),
+                build_stat_section(HitResult::Good.name(), data.play.count_judgements(HitResult::Good).to_string(), &font),
+                build_stat_section("Mods", if results.record.mods.is_empty() { "None".to_owned() } else { results.record.mods.join(" ") }, &font),
+                build_stat_section("Mean offset", match mean_hit_offset(&data.play.judgements) {
+                    Some(offset) => format!("{:+.1}ms", offset),
+                    None => "-".to_owned(),
+                }, &font),
+            ]));
+            builder.spawn(TextBundle {
+                style: Style {
+                    margin: UiRect::px(40., 10., 10., 10.),
+                    ..default()
+                },
+                text: build_top_scores_text(&db, &results.record, &font),
+                ..default()
+            });
+        });
+
+        builder.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                margin: UiRect::px(10., 10., 10., 10.),
+                ..default()
+            },
+            ..default()
+        }).with_children(|builder| {
+            build_hit_histogram(builder, &data.play.judgements, &data.play.windows(), &font);
+            build_accuracy_graph(builder, &data.play.judgements, &font);
+        });
+
+        builder.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                margin: UiRect::px(10., 10., 10., 10.),
+                ..default()
+            },
+            ..default()
+        }).with_children(|builder| {
+            build_results_button(builder, ResultsButton::Retry, "Retry (R)");
+            build_results_button(builder, ResultsButton::Watch, "Watch replay (W)");
+            build_results_button(builder, ResultsButton::Back, "Song select (Esc)");
+        });
+    });
+
+    commands.spawn((Camera2dBundle {
+        camera: Camera {
+            clear_color: ClearColorConfig::Custom(Color::rgb(0., 0., 0.)),
+            ..default()
+        },
+        ..default()
+    }, OnResults));
+}
+
+fn build_failed_results(data: Res<PlayStateData>, globals: Res<GlobalAssets>, mut commands: Commands) {
+    let font = globals.main_font.clone();
+    // In song time, like the map length
+    let failed_at_ms = (data.play.failed_at_ms.unwrap_or(0) as f32 * data.play_speed) as i128;
+    let length_ms = data.note_data.length_ms().max(1);
+    let progress = (failed_at_ms as f32 / length_ms as f32 * 100.).clamp(0., 100.);
+
+    commands.spawn((NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        ..default()
+    }, OnResults)).with_children(|builder| {
+        builder.spawn(TextBundle {
+            style: Style {
+                margin: UiRect::px(10., 10., 10., 20.),
+                ..default()
+            },
+            text: Text::from_sections([
+                TextSection::new("FAILED", TextStyle {
+                    font_size: 80.,
+                    color: Color::hex("#c41d31").unwrap(),
+                    font: font.clone(),
+                }),
+                TextSection::new("\n".to_owned() + &data.map.title, TextStyle {
+                    font_size: 30.,
+                    color: Color::rgb(0.851, 0.247, 0.269),
+                    font: font.clone(),
+                }),
+                TextSection::new(format!("\nFailed at {0} of {1} ({2:.0}%)", format_length(failed_at_ms), format_length(length_ms), progress), TextStyle {
+                    font_size: 20.,
+                    color: Color::rgb(0.7, 0.7, 0.7),
+                    font: font.clone(),
+                }),
+            ]).with_justify(bevy::text::JustifyText::Center),
+            ..default()
+        });
+        builder.spawn(TextBundle::from_sections([
+            build_stat_section("Accuracy", format!("{:.2}%", data.play.accuracy()), &font),
+            build_stat_section("Max combo", data.play.max_combo.to_string(), &font),
+            build_stat_section("Hits", format!("{0}/{1}", data.play.hits, data.note_data.0.len()), &font),
+            build_stat_section("Misses", data.play.misses.to_string(), &font),
+            build_stat_section("Mods", if data.modifiers.enabled().is_empty() { "None".to_owned() } else { data.modifiers.acronyms().join(" ") }, &font),
+        ]));
+        builder.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                margin: UiRect::px(10., 10., 10., 10.),
+                ..default()
+            },
+            ..default()
+        }).with_children(|builder| {
+            build_accuracy_graph(builder, &data.play.judgements, &font);
+        });
+        builder.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                margin: UiRect::px(10., 10., 10., 10.),
+                ..default()
+            },
+            ..default()
+        }).with_children(|builder| {
+            build_results_button(builder, ResultsButton::Retry, "Retry (R)");
+            build_results_button(builder, ResultsButton::Watch, "Watch replay (W)");
+            build_results_button(builder, ResultsButton::Back, "Song select (Esc)");
+        });
+    });
+
+    commands.spawn((Camera2dBundle {
+        camera: Camera {
+            clear_color: ClearColorConfig::Custom(Color::rgb(0., 0., 0.)),
+            ..default()
+        },
+        ..default()
+    }, OnResults));
+}
+
+fn build_stat_section(name: &str, value: String, font: &Handle<Font>) -> TextSection {
+    TextSection::new(format!("{0}: {1}\n", name, value), TextStyle {
+        font_size: 26.,
+        color: Color::WHITE,
+        font: font.clone(),
+    })
+}
+
+fn build_best_text(results: &ResultsScore) -> String {
+    match results.previous_best {
+        Some(best) if best >= results.record.score => format!("\nPersonal best: {0}", best.to_formatted_string(&Locale::en)),
+        Some(best) => format!("\nNew personal best! (+{0})", (results.record.score - best).to_formatted_string(&Locale::en)),
+        None => "\nNew personal best!".to_owned(),
+    }
+}
+
+// Best local scores of the map, the play that just ended is highlighted
+fn build_top_scores_text(db: &ScoreDatabase, current: &ScoreRecord, font: &Handle<Font>) -> Text {
+    let mut sections = vec![TextSection::new("Local scores\n", TextStyle {
+        font_size: 22.,
+        color: Color::WHITE,
+        font: font.clone(),
+    })];
+    for (rank, record) in db.top_scores(&current.map_id, TOP_SCORES_SHOWN).iter().enumerate() {
+        let is_current = record.date == current.date && record.score == current.score;
+        sections.push(TextSection::new(format_score_line(rank, record) + "\n", TextStyle {
+            font_size: 16.,
+            color: if is_current { Color::YELLOW } else { Color::rgb(0.8, 0.8, 0.8) },
+            font: font.clone(),
+        }));
+    }
+    return Text::from_sections(sections);
+}
+
+fn build_results_button(builder: &mut ChildBuilder, button: ResultsButton, label: &str) {
+    builder.spawn((ButtonBundle {
+        style: Style {
+            width: Val::Px(300.),
+            height: Val::Px(60.),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::px(10., 10., 10., 10.),
+            ..default()
+        },
+        background_color: BackgroundColor(Color::rgb(0.15, 0.15, 0.15)),
+        ..default()
+    }, button)).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            label,
+            TextStyle {
+                font_size: 26.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..default()
+            },
+        ));
+    });
+}
+
+// Panel with a caption and a row of bars, the heights are given in percent
+fn build_graph(builder: &mut ChildBuilder, caption: String, bars: Vec<(f32, Color)>, font: &Handle<Font>) {
+    builder.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            margin: UiRect::px(10., 10., 10., 10.),
+            ..default()
+        },
+        ..default()
+    }).with_children(|builder| {
+        builder.spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(GRAPH_WIDTH),
+                height: Val::Px(GRAPH_HEIGHT),
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::FlexEnd,
+                padding: UiRect::all(Val::Px(4.)),
+                ..default()
+            },
+            background_color: BackgroundColor(PANEL_COLOR),
+            ..default()
+        }).with_children(|builder| {
+            let bar_width = 100. / bars.len().max(1) as f32;
+            for (height, color) in bars {
+                builder.spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Percent(bar_width),
+                        height: Val::Percent(height),
+                        margin: UiRect::horizontal(Val::Px(1.)),
+                        ..default()
+                    },
+                    background_color: BackgroundColor(color),
+                    ..default()
+                });
+            }
+        });
+        builder.spawn(TextBundle::from_section(caption, TextStyle {
+            font_size: 18.,
+            color: Color::rgb(0.7, 0.7, 0.7),
+            font: font.clone(),
+        }));
+    });
+}
+
+// How many notes were hit in each slice of the hit window, from the earliest to the latest, colored by their tier
+fn build_hit_histogram(builder: &mut ChildBuilder, judgements: &[NoteJudgement], windows: &JudgementWindows, font: &Handle<Font>) {
+    let window = NOTE_EARLY_HIT_WINDOW + NOTE_LATE_HIT_WINDOW;
+    let mut bins = [0usize; HISTOGRAM_BINS];
+    for offset in judgements.iter().filter_map(|judgement| judgement.offset_ms) {
+        let bin = ((offset + NOTE_EARLY_HIT_WINDOW) * HISTOGRAM_BINS as i128 / window.max(1)).clamp(0, HISTOGRAM_BINS as i128 - 1);
+        bins[bin as usize] += 1;
+    }
+    let max = bins.iter().copied().max().unwrap_or(0).max(1);
+    let bars = bins.iter().enumerate()
+        .map(|(bin, count)| {
+            let center_ms = (bin as i128 * 2 + 1) * window / (HISTOGRAM_BINS as i128 * 2) - NOTE_EARLY_HIT_WINDOW;
+            (*count as f32 / max as f32 * 100., get_hit_result_color(windows.judge(Some(center_ms))))
+        })
+        .collect();
+    let caption = format!("Hit timing ({0}ms to +{1}ms)", -NOTE_EARLY_HIT_WINDOW, NOTE_LATE_HIT_WINDOW);
+    build_graph(builder, caption, bars, font);
+}
+
+// Accuracy from the start of the map up to each point of the map
+fn build_accuracy_graph(builder: &mut ChildBuilder, judgements: &[NoteJudgement], font: &Handle<Font>) {
+    let samples: Vec<f32> = if judgements.is_empty() {
+        vec![]
+    } else {
+        let count = ACCURACY_GRAPH_SAMPLES.min(judgements.len());
+        (1..=count).map(|i| get_accuracy_of(&judgements[..i * judgements.len() / count])).collect()
+    };
+    // The bottom of the graph is the lowest accuracy rounded down to 10%, otherwise most plays would look flat
+    let lowest = samples.iter().copied().fold(100_f32, f32::min);
+    let floor = ((lowest / 10.).floor() * 10.).min(90.);
+    let bars = samples.iter()
+        .map(|accuracy| (((accuracy - floor) / (100. - floor) * 100.).max(2.), get_play_grade_color(calc_play_grade(*accuracy))))
+        .collect();
+    build_graph(builder, format!("Accuracy over time ({0}% to 100%)", floor), bars, font);
+}
+
+fn mean_hit_offset(judgements: &[NoteJudgement]) -> Option<f32> {
+    let offsets: Vec<i128> = judgements.iter().filter_map(|judgement| judgement.offset_ms).collect();
+    if offsets.is_empty() {
+        return None;
+    }
+    return Some(offsets.iter().sum::<i128>() as f32 / offsets.len() as f32);
+}
+
+fn on_results_button(
+    interaction_query: Query<(&Interaction, &ResultsButton), (Changed<Interaction>, With<Button>)>,
+    data: Res<PlayStateData>,
+    results_replay: Res<ResultsReplay>,
+    modifiers: Res<Modifiers>,
+    mut commands: Commands,
+    mut state: ResMut<NextState<GameState>>
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            on_results_action(*button, &data, &results_replay, &modifiers, &mut commands, &mut state);
+        }
+    }
+}
+
+fn on_results_keys(
+    input: MenuInput,
+    data: Res<PlayStateData>,
+    results_replay: Res<ResultsReplay>,
+    modifiers: Res<Modifiers>,
+    mut commands: Commands,
+    mut state: ResMut<NextState<GameState>>
+) {
+    if input.just_pressed(MenuAction::Retry) {
+        on_results_action(ResultsButton::Retry, &data, &results_replay, &modifiers, &mut commands, &mut state);
+    } else if input.just_pressed(MenuAction::Watch) {
+        on_results_action(ResultsButton::Watch, &data, &results_replay, &modifiers, &mut commands, &mut state);
+    } else if input.just_pressed(MenuAction::Back) {
+        on_results_action(ResultsButton::Back, &data, &results_replay, &modifiers, &mut commands, &mut state);
+    }
+}
+
+// Retrying is played with the modifiers picked in the menu, not the ones of a watched replay
+fn on_results_action(button: ResultsButton, data: &PlayStateData, results_replay: &ResultsReplay, modifiers: &Modifiers, commands: &mut Commands, state: &mut NextState<GameState>) {
+    match button {
+        ResultsButton::Retry => commands.insert_resource(MapLoadPlayResource::create_loaded(data.map.clone(), modifiers.clone())),
+        ResultsButton::Watch => commands.insert_resource(MapLoadPlayResource::create_replay(data.map.clone(), results_replay.replay.clone())),
+        ResultsButton::Back => state.set(GameState::Menu),
+    }
+}
+
+fn cleanup_results(mut commands: Commands, query: Query<Entity, With<OnResults>>) {
+    for ent in query.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    commands.remove_resource::<ResultsScore>();
+    commands.remove_resource::<ResultsReplay>();
+}
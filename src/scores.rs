@@ -0,0 +1,218 @@
+use std::{fs, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use bevy::{ecs::system::Resource, log::{info, warn}};
+use num_format::{Locale, ToFormattedString};
+use serde::{Deserialize, Serialize};
+
+use crate::{map::NoteData, storage};
+
+const SCORES_FILE: &str = "scores.json";
+
+// A finished play of a map
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ScoreRecord {
+    // See `map_id`, the title is only kept to make the file readable
+    pub map_id: String,
+    pub map_title: String,
+    pub score: i128,
+    pub accuracy: f32,
+    pub grade: String,
+    pub max_combo: i128,
+    pub hits: i128,
+    pub misses: i128,
+    pub mods: Vec<String>,
+    pub speed: f32,
+    // Unix time in seconds
+    pub date: u64,
+    // File name of the replay in the replays folder
+    #[serde(default)]
+    pub replay: Option<String>,
+}
+
+// Every play saved on this computer, stored as json in the user data directory.
+#[derive(Resource, Serialize, Deserialize, Default)]
+pub struct ScoreDatabase {
+    pub scores: Vec<ScoreRecord>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl ScoreDatabase {
+    // Never fails, an unreadable database is replaced by an empty one so the game stays playable
+    pub fn load() -> ScoreDatabase {
+        let Some(path) = storage::data_dir().map(|dir| dir.join(SCORES_FILE)) else {
+            warn!("No data directory on this platform, scores will not be saved");
+            return ScoreDatabase::default();
+        };
+        let mut db = match fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice::<ScoreDatabase>(&bytes) {
+                Ok(db) => db,
+                Err(err) => {
+                    warn!("Could not read the score database {0}: {1}", path.display(), err);
+                    ScoreDatabase::default()
+                }
+            },
+            Err(_) => ScoreDatabase::default(),
+        };
+        info!("Loaded {0} scores from {1}", db.scores.len(), path.display());
+        db.path = Some(path);
+        return db;
+    }
+
+    pub fn add(&mut self, record: ScoreRecord) {
+        self.scores.push(record);
+        self.save();
+    }
+
+    // Written to a temporary file first so a crash while saving can't lose every score
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_vec_pretty(self)
+            .map_err(std::io::Error::from)
+            .and_then(|json| storage::write_file(path, &json));
+        if let Err(err) = result {
+            warn!("Could not save the score database {0}: {1}", path.display(), err);
+        }
+    }
+
+    // Best scores first
+    pub fn top_scores(&self, map_id: &str, count: usize) -> Vec<&ScoreRecord> {
+        let mut scores: Vec<&ScoreRecord> = self.scores.iter().filter(|record| record.map_id == map_id).collect();
+        scores.sort_by(|a, b| b.score.cmp(&a.score).then(a.date.cmp(&b.date)));
+        scores.truncate(count);
+        return scores;
+    }
+
+    pub fn personal_best(&self, map_id: &str) -> Option<&ScoreRecord> {
+        return self.top_scores(map_id, 1).into_iter().next();
+    }
+}
+
+// Maps are identified by their notes (FNV-1a hash) so that renaming or moving a map keeps its scores.
+pub fn map_id(notes: &NoteData) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for note in &notes.0 {
+        let fields = [(note.hit_ms as i64).to_le_bytes(), (note.x.to_bits() as u64).to_le_bytes(), (note.y.to_bits() as u64).to_le_bytes(), (note.size.to_bits() as u64).to_le_bytes()];
+        for byte in fields.iter().flatten() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    return format!("{:016x}", hash);
+}
+
+pub fn now_unix() -> u64 {
+    return SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+}
+
+// One line of a local scores list, the rank starts at 0
+pub fn format_score_line(rank: usize, record: &ScoreRecord) -> String {
+    let mut line = format!("#{0} {1} {2:.2}% {3} x{4:.2}", rank + 1, record.score.to_formatted_string(&Locale::en), record.accuracy, record.grade, record.speed);
+    if !record.mods.is_empty() {
+        line += &(" ".to_owned() + &record.mods.join(" "));
+    }
+    return line + " " + &format_date(record.date);
+}
+
+// Formats a unix time as "YYYY-MM-DD HH:MM" (UTC)
+pub fn format_date(unix_seconds: u64) -> String {
+    let days = (unix_seconds / 86400) as i64;
+    let minutes = (unix_seconds % 86400) / 60;
+    // Days to civil date, from Howard Hinnant's date algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    return format!("{0}-{1:02}-{2:02} {3:02}:{4:02}", year, month, day, minutes / 60, minutes % 60);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{engine::Note, map::NoteData};
+
+    use super::{format_date, map_id, ScoreDatabase, ScoreRecord};
+
+    fn record(map_id: &str, score: i128, date: u64) -> ScoreRecord {
+        return ScoreRecord {
+            map_id: map_id.to_owned(),
+            map_title: "Artist - Song".to_owned(),
+            score,
+            accuracy: 95.,
+            grade: "A".to_owned(),
+            max_combo: 10,
+            hits: 10,
+            misses: 0,
+            mods: vec![],
+            speed: 1.,
+            date,
+            replay: None,
+        };
+    }
+
+    fn database(records: Vec<ScoreRecord>) -> ScoreDatabase {
+        return ScoreDatabase { scores: records, path: None };
+    }
+
+    #[test]
+    fn dates_are_formatted_in_utc() {
+        let cases = [
+            (0, "1970-01-01 00:00"),
+            (59, "1970-01-01 00:00"),
+            (946684740, "1999-12-31 23:59"),
+            (951782400, "2000-02-29 00:00"),
+            (1700000000, "2023-11-14 22:13"),
+            (1709251140, "2024-02-29 23:59"),
+            // 2100 is not a leap year
+            (4107587400, "2100-03-01 12:30"),
+        ];
+        for (unix_seconds, expected) in cases {
+            assert_eq!(format_date(unix_seconds), expected, "{0}", unix_seconds);
+        }
+    }
+
+    #[test]
+    fn top_scores_are_best_first_then_oldest_first() {
+        let db = database(vec![
+            record("a", 500, 3),
+            record("b", 900, 1),
+            record("a", 700, 5),
+            record("a", 700, 2),
+            record("a", 100, 1),
+        ]);
+        let top: Vec<(i128, u64)> = db.top_scores("a", 3).iter().map(|record| (record.score, record.date)).collect();
+        assert_eq!(top, vec![(700, 2), (700, 5), (500, 3)]);
+        assert_eq!(db.top_scores("a", 10).len(), 4);
+        assert!(db.top_scores("c", 10).is_empty());
+    }
+
+    #[test]
+    fn personal_best_is_the_top_score() {
+        let db = database(vec![record("a", 500, 1), record("a", 800, 9), record("a", 800, 4), record("b", 1000, 1)]);
+        let best = db.personal_best("a").unwrap();
+        assert_eq!((best.score, best.date), (800, 4));
+        assert!(db.personal_best("c").is_none());
+    }
+
+    #[test]
+    fn map_ids_stay_the_same() {
+        // Saved scores and replays point to their map with this id, changing the hash orphans them
+        let notes = NoteData(vec![
+            Note { hit_ms: 100, x: 0., y: 0., size: 1. },
+            Note { hit_ms: 250, x: -1., y: 1., size: 1. },
+            Note { hit_ms: 400, x: 0.5, y: -0.25, size: 1.5 },
+        ]);
+        assert_eq!(map_id(&notes), "a87382fb72dd9d6e");
+        assert_eq!(map_id(&NoteData(vec![])), "cbf29ce484222325");
+        // Any change to the notes is another map
+        let mut moved = notes.clone();
+        moved.0[1].hit_ms += 1;
+        assert_ne!(map_id(&moved), map_id(&notes));
+    }
+}
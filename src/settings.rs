@@ -0,0 +1,259 @@
+use std::{fs, path::PathBuf};
+
+use bevy::{ecs::system::Resource, log::{info, warn}, render::color::Color};
+use serde::{Deserialize, Serialize};
+
+use crate::{engine::{autoplay::AutoplayCurve, judgement::JudgementWindows}, storage};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+// Note color palettes that can be picked in the settings screen, any other list of colors can be set in the settings file
+pub const NOTE_PALETTES: [(&str, &[&str]); 4] = [
+    ("Wii", &["#008dfeff", "#ed3434ff", "#11bd0cff", "#feb200ff"]),
+    ("Sound Space", &["#00ffedff", "#ff0099ff"]),
+    ("Sunset", &["#ff5e62ff", "#ff9966ff", "#ffd166ff"]),
+    ("Mono", &["#ffffffff"]),
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum CursorMode {
+    // The cursor is moved by the mouse movement
+    Relative,
+    // The position of the mouse on the window is mapped onto the cursor area, for tablets
+    Absolute,
+    // The cursor snaps to the grid cells with the numpad or QWE/ASD/ZXC
+    Keyboard
+}
+
+// What happens when the cursor reaches the edge of its area
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum CursorEdge {
+    // The cursor stops at the edge and the extra movement is lost
+    Border,
+    // The cursor stops at the edge but the movement past it is kept, it only comes back once the mouse has moved back
+    Drift
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum CursorArea {
+    // The play grid
+    Grid,
+    // Everything the play camera sees at the grid
+    Camera
+}
+
+// Player settings, stored as json in the user config directory.
+// Missing fields keep their default value so that older settings files still load.
+#[derive(Resource, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Settings {
+    // How long a note is visible before it has to be hit (ms)
+    pub approach_rate: i128,
+    // How far away from the grid notes spawn
+    pub approach_distance: f32,
+    // How long notes take to fade in after spawning (ms)
+    pub fade_in: i128,
+    // Time before the first note and after the last one (ms)
+    pub start_finish_wait: i128,
+    // Largest hit offsets (ms) for a Perfect and a Great, later hits are Good
+    pub perfect_window: i128,
+    pub great_window: i128,
+    // Cursor movement multiplier, in absolute mode it scales the distance from the center of the window
+    pub sensitivity: f32,
+    pub cursor_mode: CursorMode,
+    pub cursor_edge: CursorEdge,
+    pub cursor_area: CursorArea,
+    // Controller sticks move the cursor in relative mode, the deadzone goes from 0 to 1
+    pub stick_deadzone: f32,
+    // Grid cells per second with the stick fully tilted
+    pub stick_speed: f32,
+    // Power curve of the stick, 1 is linear, higher is slower near the center
+    pub stick_acceleration: f32,
+    pub autoplay_curve: AutoplayCurve,
+    // Volumes go from 0 to 1, the music and hit sound volumes are multiplied by the master volume
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub hit_volume: f32,
+    // Hex colors the notes cycle through
+    pub note_palette: Vec<String>,
+    // Vertical field of view of the play camera (degrees)
+    pub fov: f32,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            approach_rate: 500,
+            approach_distance: 25.,
+            fade_in: 400,
+            start_finish_wait: 500,
+            perfect_window: 50,
+            great_window: 100,
+            sensitivity: 1.,
+            cursor_mode: CursorMode::Relative,
+            cursor_edge: CursorEdge::Border,
+            cursor_area: CursorArea::Grid,
+            stick_deadzone: 0.15,
+            stick_speed: 4.,
+            stick_acceleration: 2.,
+            autoplay_curve: AutoplayCurve::Eased,
+            master_volume: 1.,
+            music_volume: 1.,
+            hit_volume: 1.,
+            note_palette: NOTE_PALETTES[0].1.iter().map(|color| color.to_string()).collect(),
+            fov: 70.,
+            path: None,
+        }
+    }
+}
+
+impl Settings {
+    // Never fails, unreadable settings are replaced by the default ones
+    pub fn load() -> Settings {
+        let Some(path) = storage::config_dir().map(|dir| dir.join(SETTINGS_FILE)) else {
+            warn!("No config directory on this platform, settings will not be saved");
+            return Settings::default();
+        };
+        return Settings::load_from(path);
+    }
+
+    fn load_from(path: PathBuf) -> Settings {
+        let mut settings = match fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice::<Settings>(&bytes) {
+                Ok(settings) => {
+                    info!("Loaded settings from {0}", path.display());
+                    settings
+                }
+                Err(err) => {
+                    warn!("Could not read the settings {0}: {1}", path.display(), err);
+                    Settings::default()
+                }
+            },
+            Err(_) => Settings::default(),
+        };
+        settings.normalize_windows();
+        settings.path = Some(path);
+        return settings;
+    }
+
+    // Edited settings files can have any window, they are brought back to ones the game allows
+    pub fn normalize_windows(&mut self) {
+        let windows = self.judgement_windows().normalized();
+        self.perfect_window = windows.perfect_ms;
+        self.great_window = windows.great_ms;
+    }
+
+    // Like the scores, a crash while saving can't leave a broken settings file
+    pub fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_vec_pretty(self)
+            .map_err(std::io::Error::from)
+            .and_then(|json| storage::write_file(path, &json));
+        if let Err(err) = result {
+            warn!("Could not save the settings {0}: {1}", path.display(), err);
+        }
+    }
+
+    // Back to the default settings, still saved to the same file
+    pub fn reset(&mut self) {
+        *self = Settings {
+            path: self.path.take(),
+            ..Settings::default()
+        };
+    }
+
+    // Notes can't take longer to fade in than they are visible
+    pub fn note_fade_in(&self) -> i128 {
+        return self.fade_in.clamp(1, self.approach_rate.max(1));
+    }
+
+    pub fn judgement_windows(&self) -> JudgementWindows {
+        JudgementWindows {
+            perfect_ms: self.perfect_window,
+            great_ms: self.great_window,
+        }
+    }
+
+    pub fn music_volume(&self) -> f64 {
+        return (self.master_volume * self.music_volume) as f64;
+    }
+
+    pub fn hit_volume(&self) -> f64 {
+        return (self.master_volume * self.hit_volume) as f64;
+    }
+
+    // Invalid colors are skipped, the default palette is used if none are left
+    pub fn note_palette_colors(&self) -> Vec<Color> {
+        let colors: Vec<Color> = self.note_palette.iter()
+            .filter_map(|color| Color::hex(color).map_err(|_| warn!("Invalid note color {0} in the settings", color)).ok())
+            .collect();
+        if colors.is_empty() {
+            return NOTE_PALETTES[0].1.iter().map(|color| Color::hex(color).unwrap()).collect();
+        }
+        return colors;
+    }
+
+    // The preset matching the current palette, None for a custom one
+    pub fn note_palette_preset(&self) -> Option<usize> {
+        return NOTE_PALETTES.iter().position(|(_, colors)| colors.iter().copied().eq(self.note_palette.iter().map(|color| color.as_str())));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::engine::judgement::{MIN_JUDGEMENT_WINDOW, NOTE_LATE_HIT_WINDOW};
+
+    use super::Settings;
+
+    // Loads the settings from a file with these contents
+    fn load(name: &str, contents: &str) -> Settings {
+        let path: PathBuf = std::env::temp_dir().join(format!("soundaim-settings-{0}-{1}.json", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let settings = Settings::load_from(path.clone());
+        fs::remove_file(path).unwrap();
+        return settings;
+    }
+
+    fn windows(settings: &Settings) -> (i128, i128) {
+        return (settings.perfect_window, settings.great_window);
+    }
+
+    #[test]
+    fn windows_from_a_corrupt_file_are_normalized() {
+        let cases = [
+            // Perfect wider than great
+            (r#"{ "perfect_window": 150, "great_window": 60 }"#, (60, 60)),
+            (r#"{ "perfect_window": 0, "great_window": 0 }"#, (MIN_JUDGEMENT_WINDOW, MIN_JUDGEMENT_WINDOW)),
+            (r#"{ "perfect_window": -40, "great_window": 80 }"#, (MIN_JUDGEMENT_WINDOW, 80)),
+            (r#"{ "perfect_window": 90, "great_window": 100000 }"#, (90, NOTE_LATE_HIT_WINDOW)),
+            (r#"{ "perfect_window": 100000, "great_window": -100000 }"#, (MIN_JUDGEMENT_WINDOW, MIN_JUDGEMENT_WINDOW)),
+            (r#"{ "great_window": 30 }"#, (30, 30)),
+        ];
+        for (i, (contents, expected)) in cases.into_iter().enumerate() {
+            let settings = load(&format!("windows-{0}", i), contents);
+            assert_eq!(windows(&settings), expected, "{0}", contents);
+        }
+    }
+
+    #[test]
+    fn unreadable_file_gives_the_default_settings() {
+        let default = windows(&Settings::default());
+        for (i, contents) in ["", "{", "not json", r#"{ "perfect_window": "fast" }"#, "[1, 2, 3]"].into_iter().enumerate() {
+            let settings = load(&format!("unreadable-{0}", i), contents);
+            assert_eq!(windows(&settings), default, "{0:?}", contents);
+            assert!(settings.path.is_some());
+        }
+    }
+
+    #[test]
+    fn valid_windows_are_kept() {
+        let settings = load("valid", r#"{ "perfect_window": 35, "great_window": 120 }"#);
+        assert_eq!(windows(&settings), (35, 120));
+    }
+}
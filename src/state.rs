@@ -1,12 +1,15 @@
 use bevy::{app::Plugin, ecs::schedule::States};
 
-use crate::{menu::menu_state::MenuStatePlugin, play::play_state::PlayStatePlugin, startup::StartupPlugin};
+use crate::{loading::LoadingPlugin, menu::{menu_state::MenuStatePlugin, settings_state::SettingsStatePlugin}, play::play_state::PlayStatePlugin, results::results_state::ResultsStatePlugin, startup::StartupPlugin};
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 pub (crate) enum GameState {
     #[default] Startup,
+    Loading,
     Menu,
-    Play
+    Play,
+    Results,
+    Settings
 }
 
 pub struct StatePlugin;
@@ -19,8 +22,11 @@ impl Plugin for StatePlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.init_state::<GameState>().add_plugins((
             StartupPlugin,
+            LoadingPlugin,
             MenuStatePlugin,
-            PlayStatePlugin
+            PlayStatePlugin,
+            ResultsStatePlugin,
+            SettingsStatePlugin
         ));
     }
 }
\ No newline at end of file
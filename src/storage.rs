@@ -0,0 +1,45 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+// Folder of the game in the user data and config directories
+const APP_DIR: &str = "soundaim";
+
+// Where the scores and replays are kept
+pub fn data_dir() -> Option<PathBuf> {
+    return dirs::data_dir().map(|dir| dir.join(APP_DIR));
+}
+
+// Where the settings are kept
+#[cfg(feature = "client")]
+pub fn config_dir() -> Option<PathBuf> {
+    return dirs::config_dir().map(|dir| dir.join(APP_DIR));
+}
+
+// Written to a temporary file first, then moved over the old one, so a crash while saving can't leave half a file
+pub fn write_file(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, bytes)?;
+    return fs::rename(&tmp_path, path);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::write_file;
+
+    #[test]
+    fn written_files_replace_the_old_ones() {
+        let dir = std::env::temp_dir().join(format!("soundaim-storage-{0}", std::process::id()));
+        let path = dir.join("nested").join("file.json");
+        write_file(&path, b"old").unwrap();
+        write_file(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        // Only the file itself is left
+        assert_eq!(fs::read_dir(dir.join("nested")).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use std::path::Path;
+
+use soundaim::{engine::{autoplay::{run_autoplay, AUTOPLAY_CURVES}, get_play_notes, judgement::{HitResult, JudgementWindows}}, map::{read_map_file, MapFile}, modifiers::Modifiers};
+
+// 60 to 240 FPS, slower frame rates can't hit notes hit together on opposite sides of the grid within the Perfect window
+const FRAME_MS: [i128; 3] = [16, 7, 4];
+const SLOW_FRAME_MS: i128 = 33;
+const APPROACH_RATES: [i128; 2] = [200, 500];
+const MAPS: [&str; 6] = ["dense.txt", "stacked.txt", "edges.txt", "dense.sspm", "stacked.sspm", "edges.sspm"];
+
+fn read_fixture(name: &str) -> MapFile {
+    return read_map_file(&Path::new("tests/fixtures").join(name)).unwrap_or_else(|err| panic!("{0}: {1}", name, err));
+}
+
+fn modifier_sets() -> Vec<Modifiers> {
+    return vec![
+        Modifiers::default(),
+        Modifiers { mirror: true, ..Modifiers::default() },
+        Modifiers { mirror: true, flip: true, ..Modifiers::default() },
+        Modifiers { hard_rock: true, ..Modifiers::default() },
+        Modifiers { speed: 1.5, ..Modifiers::default() },
+        Modifiers { speed: 0.5, no_fail: true, ..Modifiers::default() },
+    ];
+}
+
+#[test]
+fn autoplay_hits_every_note_perfectly() {
+    for name in MAPS {
+        let map = read_fixture(name);
+        for modifiers in modifier_sets() {
+            let notes = get_play_notes(&map.notes.0, map.metadata.offset, &modifiers);
+            for curve in AUTOPLAY_CURVES {
+                for frame_ms in FRAME_MS {
+                    for approach_rate in APPROACH_RATES {
+                        let play = run_autoplay(&notes, &modifiers, JudgementWindows::default(), curve, approach_rate, frame_ms);
+                        let case = format!("{0}, {1:?}, {2}ms frames, {3}ms approach, speed {4}, mirror {5}, flip {6}, hard rock {7}", name, curve, frame_ms, approach_rate, modifiers.speed, modifiers.mirror, modifiers.flip, modifiers.hard_rock);
+                        assert_eq!(play.judgements.len(), notes.len(), "{0}", case);
+                        assert_eq!(play.hits, notes.len() as i128, "{0}", case);
+                        assert_eq!(play.count_judgements(HitResult::Perfect), notes.len(), "{0}", case);
+                        assert_eq!(play.accuracy(), 100., "{0}", case);
+                        assert_eq!(play.max_combo, notes.len() as i128, "{0}", case);
+                        assert_eq!(play.failed_at_ms, None, "{0}", case);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn autoplay_hits_every_note_at_30_fps() {
+    for name in MAPS {
+        let map = read_fixture(name);
+        for modifiers in modifier_sets() {
+            let notes = get_play_notes(&map.notes.0, map.metadata.offset, &modifiers);
+            for curve in AUTOPLAY_CURVES {
+                let play = run_autoplay(&notes, &modifiers, JudgementWindows::default(), curve, 500, SLOW_FRAME_MS);
+                assert_eq!(play.hits, notes.len() as i128, "{0}, {1:?}, speed {2}", name, curve, modifiers.speed);
+            }
+        }
+    }
+}
+
+#[test]
+fn sspm_fixtures_have_the_notes_of_the_v1_ones() {
+    for name in ["dense", "stacked", "edges"] {
+        let (v1, sspm) = (read_fixture(&format!("{0}.txt", name)), read_fixture(&format!("{0}.sspm", name)));
+        assert_eq!(v1.notes.0, sspm.notes.0, "{0}", name);
+    }
+}
@@ -0,0 +1,187 @@
+use std::{fs, path::{Path, PathBuf}, process::{Command, Output}};
+
+use soundaim::{engine::{autoplay::{run_autoplay, AutoplayCurve}, get_play_notes, judgement::JudgementWindows}, map::{read_map_file, MapFile}, modifiers::{Modifiers, MAX_SPEED}, replay::Replay, scores::map_id};
+
+const BIRB: &str = "assets/maps/birb/ss_archive_belowamateur_-_birb.txt";
+const BIRB_AUDIO: &str = "assets/maps/birb/ss_archive_belowamateur_-_birb.mp3";
+
+fn cli(args: &[&str]) -> Output {
+    return Command::new(env!("CARGO_BIN_EXE_soundaim-cli")).args(args).output().expect("soundaim-cli did not run");
+}
+
+// An empty directory for the files of one test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("soundaim-cli-{0}-{1}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    return dir;
+}
+
+fn path_str(path: &Path) -> &str {
+    return path.to_str().unwrap();
+}
+
+fn read(path: &Path) -> MapFile {
+    return read_map_file(path).unwrap_or_else(|err| panic!("{0}: {1}", path.display(), err));
+}
+
+fn assert_same_map(expected: &MapFile, actual: &MapFile) {
+    assert_eq!(actual.notes.0, expected.notes.0);
+    assert_eq!(map_id(&actual.notes), map_id(&expected.notes));
+    let (expected, actual) = (&expected.metadata, &actual.metadata);
+    assert_eq!(
+        (&actual.title, &actual.artist, &actual.mapper, &actual.difficulty, actual.offset),
+        (&expected.title, &expected.artist, &expected.mapper, &expected.difficulty, expected.offset)
+    );
+}
+
+#[test]
+fn convert_round_trips_v1_through_sspm() {
+    let dir = temp_dir("round-trip");
+    let original = read(Path::new(BIRB));
+    let audio = fs::read(BIRB_AUDIO).unwrap();
+
+    let sspm_path = dir.join("birb.sspm");
+    let output = cli(&["convert", BIRB, path_str(&sspm_path)]);
+    assert!(output.status.success(), "{0}", String::from_utf8_lossy(&output.stderr));
+    let sspm = read(&sspm_path);
+    assert_same_map(&original, &sspm);
+    assert_eq!(sspm.audio.as_ref(), Some(&audio));
+
+    // Back to a map folder
+    fs::create_dir(dir.join("back")).unwrap();
+    let v1_path = dir.join("back").join("birb.txt");
+    let output = cli(&["convert", path_str(&sspm_path), path_str(&v1_path)]);
+    assert!(output.status.success(), "{0}", String::from_utf8_lossy(&output.stderr));
+    let v1 = read(&v1_path);
+    assert_same_map(&original, &v1);
+    assert_eq!(v1.metadata.audio.as_deref(), Some("birb.mp3"));
+    assert_eq!(fs::read(dir.join("back").join("birb.mp3")).unwrap(), audio);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn convert_to_sspm_adds_the_offset_to_the_notes() {
+    let dir = temp_dir("offset");
+    fs::write(dir.join("map.txt"), "1234,1|1|100,2|0|250").unwrap();
+    fs::write(dir.join("map.json"), r#"{ "title": "Song", "artist": "Artist", "offset": 120 }"#).unwrap();
+    let sspm_path = dir.join("map.sspm");
+    assert!(cli(&["convert", path_str(&dir.join("map.txt")), path_str(&sspm_path)]).status.success());
+
+    let sspm = read(&sspm_path);
+    let times: Vec<i128> = sspm.notes.0.iter().map(|note| note.hit_ms).collect();
+    assert_eq!(times, vec![220, 370]);
+    assert_eq!(sspm.metadata.offset, 0);
+    assert_eq!((sspm.metadata.artist.as_str(), sspm.metadata.title.as_str()), ("Artist", "Song"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// Plays the map with autoplay and keeps the result like the game does when saving a replay
+fn autoplay_replay(map: &MapFile) -> Replay {
+    let modifiers = Modifiers::default();
+    let windows = JudgementWindows::default();
+    let notes = get_play_notes(&map.notes.0, map.metadata.offset, &modifiers);
+    let play = run_autoplay(&notes, &modifiers, windows, AutoplayCurve::Eased, 500, 16);
+    return Replay {
+        map_id: map_id(&map.notes),
+        map_title: map.metadata.title.clone(),
+        date: 1700000000,
+        approach_rate: 500,
+        approach_distance: 10.,
+        fade_in: 100,
+        start_finish_wait: 1000,
+        windows,
+        modifiers,
+        score: play.score(),
+        accuracy: play.accuracy(),
+        hits: play.hits,
+        misses: play.misses,
+        max_combo: play.max_combo,
+        failed: play.failed_at_ms.is_some(),
+        frames: play.frames,
+    };
+}
+
+fn save_replay(replay: &Replay, path: &Path) {
+    let mut bytes = vec![];
+    replay.write(&mut bytes).unwrap();
+    fs::write(path, bytes).unwrap();
+}
+
+#[test]
+fn verify_accepts_recorded_replays_and_rejects_edited_ones() {
+    let dir = temp_dir("verify");
+    let replay = autoplay_replay(&read(Path::new(BIRB)));
+    let replay_path = dir.join("birb.sarp");
+
+    save_replay(&replay, &replay_path);
+    let output = cli(&["verify", BIRB, path_str(&replay_path)]);
+    assert_eq!(output.status.code(), Some(0), "{0}", String::from_utf8_lossy(&output.stdout));
+
+    let edits = [
+        ("score", Replay { score: replay.score + 1000, ..replay.clone() }),
+        ("hits", Replay { hits: replay.hits - 1, ..replay.clone() }),
+        // Settings the game doesn't allow, rejected even though the saved result matches them
+        ("autoplay", Replay { modifiers: Modifiers { autoplay: true, ..replay.modifiers.clone() }, ..replay.clone() }),
+        ("no speed", Replay { modifiers: Modifiers { speed: 0., ..replay.modifiers.clone() }, ..replay.clone() }),
+        ("too fast", Replay { modifiers: Modifiers { speed: MAX_SPEED + 0.5, ..replay.modifiers.clone() }, ..replay.clone() }),
+        ("nan speed", Replay { modifiers: Modifiers { speed: f32::NAN, ..replay.modifiers.clone() }, ..replay.clone() }),
+        ("huge windows", Replay { windows: JudgementWindows { perfect_ms: 1000, great_ms: 1000 }, ..replay.clone() }),
+        ("tiny windows", Replay { windows: JudgementWindows { perfect_ms: 1, great_ms: 100 }, ..replay.clone() }),
+        ("perfect over great", Replay { windows: JudgementWindows { perfect_ms: 80, great_ms: 50 }, ..replay.clone() }),
+    ];
+    for (name, edited) in edits {
+        save_replay(&edited, &replay_path);
+        let output = cli(&["verify", BIRB, path_str(&replay_path)]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(output.status.code(), Some(1), "{0}: {1}", name, stdout);
+        assert!(stdout.contains("Not verified"), "{0}: {1}", name, stdout);
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn validate_accepts_good_maps() {
+    let output = cli(&["validate", "assets/maps", "tests/fixtures"]);
+    assert_eq!(output.status.code(), Some(0), "{0}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn validate_rejects_broken_maps() {
+    let dir = temp_dir("validate");
+    let cases: [(&str, &[u8], &str); 8] = [
+        ("empty.txt", b"", "Map has no notes"),
+        ("truncated.txt", b"1234,1|1|100,1|1", "Note 1 is missing its hit time field (byte 16)"),
+        ("not_a_number.txt", b"1234,1|one|100", "Note 0 has an invalid y value \"one\" (byte 7)"),
+        ("outside.txt", b"1234,1|1|100,5|1|200", "Note 1 is outside of the grid (4, 0)"),
+        ("early.txt", b"1234,1|1|-50,1|1|100", "Note 0 is hit before the song starts (-50ms)"),
+        ("duplicate.txt", b"1234,1|1|100,0|0|100,1|1|100", "is a duplicate of note"),
+        ("garbage.sspm", b"not a map", "Not a SSPM file"),
+        ("truncated.sspm", b"SS+m\x02\x00\x00\x00", "Unexpected end of file"),
+    ];
+    for (name, contents, problem) in cases {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        let output = cli(&["validate", path_str(&path)]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(output.status.code(), Some(1), "{0}: {1}", name, stdout);
+        assert!(stdout.contains(problem), "{0}: {1}", name, stdout);
+    }
+
+    // Any broken map fails the whole check
+    let output = cli(&["validate", "tests/fixtures", path_str(&dir)]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&format!("8 of {0} maps have problems", 8 + 6)));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn missing_files_are_errors() {
+    assert_eq!(cli(&["validate", "does/not/exist.txt"]).status.code(), Some(2));
+    assert_eq!(cli(&["validate"]).status.code(), Some(2));
+    assert_eq!(cli(&["convert", "does/not/exist.txt", "out.sspm"]).status.code(), Some(2));
+}